@@ -0,0 +1,159 @@
+/// Ways of controlling a display's brightness and other features. Values are identified by VCP
+/// code, whether or not the backend actually uses DDC/CI.
+use crate::monitor::BRIGHTNESS;
+
+use ddc::{self, ConvertToAnyhow};
+
+use std::fmt::Debug;
+
+/// Reads and writes feature values of a single display.
+pub trait Backend: Debug + Send {
+    /// Read the current value of a feature.
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error>;
+
+    /// Write a feature once, without retrying.
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error>;
+
+    /// Whether this holds a DDC/CI display handle, which redetecting displays invalidates.
+    fn uses_ddc(&self) -> bool {
+        false
+    }
+}
+
+/// External monitor controlled over DDC/CI.
+#[derive(Debug)]
+pub struct Ddc(pub ddc::Display);
+
+impl Backend for Ddc {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        Ok(self.0.get_vcp_value(code).anyhow()?.value())
+    }
+
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        self.0.set_vcp_value(code, value).anyhow()
+    }
+
+    fn uses_ddc(&self) -> bool {
+        true
+    }
+}
+
+/// Stands in for a display whose connection was closed, failing every read and write.
+#[derive(Debug)]
+pub struct Closed;
+
+impl Backend for Closed {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        anyhow::bail!("Can't read VCP feature {code:#04x}, the display was closed")
+    }
+
+    fn set(&mut self, code: u8, _value: u16) -> Result<(), anyhow::Error> {
+        anyhow::bail!("Can't set VCP feature {code:#04x}, the display was closed")
+    }
+}
+
+/// Monitor whose brightness range is extended below 0% by dimming in software once the hardware
+/// brightness reaches 0.
+///
+/// Brightness is shifted up by `range`: values from `range` to `100 + range` are hardware brightness
+/// 0 to 100 with the software brightness at 100, and values below `range` keep the hardware at 0 and
+/// lower the software brightness by one percent each. Other features only go to the hardware.
+#[derive(Debug)]
+pub struct Extended {
+    hardware: Box<dyn Backend>,
+    software: Box<dyn Backend>,
+    range: u16,
+    /// Last (hardware, software) brightness written, to avoid redundant writes
+    last: Option<(u16, u16)>,
+}
+
+impl Extended {
+    pub fn new(hardware: Box<dyn Backend>, software: Box<dyn Backend>, range: u16) -> Self {
+        Extended {
+            hardware,
+            software,
+            range: range.min(100),
+            last: None,
+        }
+    }
+}
+
+impl Backend for Extended {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        if code != BRIGHTNESS {
+            return self.hardware.get(code);
+        }
+
+        let hardware = self.hardware.get(code)?;
+        if hardware > 0 {
+            return Ok(hardware + self.range);
+        }
+        let software = self.software.get(code)?;
+        Ok((software + self.range).saturating_sub(100))
+    }
+
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        if code != BRIGHTNESS {
+            return self.hardware.set(code, value);
+        }
+
+        let (hardware, software) = match value.checked_sub(self.range) {
+            Some(hardware) => (hardware, 100),
+            None => (0, 100 - (self.range - value)),
+        };
+        let (last_hardware, last_software) = self.last.unzip();
+
+        // Forget what was written if anything fails, so both are written again next time
+        self.last = None;
+        if last_hardware != Some(hardware) {
+            self.hardware.set(code, hardware)?;
+        }
+        if last_software != Some(software) {
+            self.software.set(code, software)?;
+        }
+        self.last = Some((hardware, software));
+        Ok(())
+    }
+
+    fn uses_ddc(&self) -> bool {
+        self.hardware.uses_ddc() || self.software.uses_ddc()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::FakeBackend as Fake;
+
+    #[test]
+    fn extended_dimming() {
+        let (hardware, software) = (Fake::default(), Fake::default());
+        let mut extended =
+            Extended::new(Box::new(hardware.clone()), Box::new(software.clone()), 30);
+        let mut set = |value| {
+            extended.set(BRIGHTNESS, value).unwrap();
+            let get = |b: &Fake| b.values.lock().unwrap()[&BRIGHTNESS];
+            (
+                get(&hardware),
+                get(&software),
+                extended.get(BRIGHTNESS).unwrap(),
+            )
+        };
+
+        assert_eq!((70, 100, 100), set(100));
+        assert_eq!((1, 100, 31), set(31));
+        assert_eq!((0, 100, 30), set(30));
+        assert_eq!((0, 90, 20), set(20));
+        assert_eq!((0, 70, 0), set(0));
+        assert_eq!((50, 100, 80), set(80));
+
+        // Unchanged values aren't written again
+        assert_eq!(4, *hardware.writes.lock().unwrap());
+        assert_eq!(4, *software.writes.lock().unwrap());
+
+        // Other features only go to the hardware
+        extended.set(0x12, 40).unwrap();
+        assert_eq!(40, extended.get(0x12).unwrap());
+        assert!(!software.values.lock().unwrap().contains_key(&0x12));
+    }
+}
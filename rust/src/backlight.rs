@@ -0,0 +1,180 @@
+/// Internal panels (laptops, some all-in-ones) controlled through `/sys/class/backlight`.
+use crate::backend::Backend;
+use crate::monitor::BRIGHTNESS;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+pub const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+/// A backlight device found under `/sys/class/backlight`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacklightDevice {
+    /// Name of the device, e.g. "intel_backlight"
+    pub name: String,
+    /// Directory of the device
+    pub path: PathBuf,
+}
+
+impl BacklightDevice {
+    /// List the backlight devices in the given directory. A missing directory means there are no
+    /// backlight devices.
+    pub fn detect<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>, anyhow::Error> {
+        let dir = dir.as_ref();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to list backlight devices in {dir:?}"));
+            }
+        };
+
+        let mut devices = entries
+            .map(|e| {
+                let e = e?;
+                Ok(BacklightDevice {
+                    name: e.file_name().to_string_lossy().into_owned(),
+                    path: e.path(),
+                })
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    pub fn open(&self) -> Result<Backlight, anyhow::Error> {
+        let max = read_value(&self.path.join("max_brightness"))?;
+        if max == 0 {
+            anyhow::bail!("Backlight {0} has a max_brightness of 0", self.name);
+        }
+        Ok(Backlight {
+            path: self.path.join("brightness"),
+            max,
+            last: None,
+        })
+    }
+}
+
+/// Backlight device, whose raw brightness range `0..=max_brightness` is scaled to a percentage.
+#[derive(Debug)]
+pub struct Backlight {
+    /// The `brightness` file of the device
+    path: PathBuf,
+    max: u32,
+    /// Last raw value written, and the percentage it was written for. With a small
+    /// `max_brightness`, several percentages share a raw value, so reading it back returns the
+    /// percentage that was set rather than the one the raw value scales back to.
+    last: Option<(u32, u16)>,
+}
+
+impl Backend for Backlight {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        check_code(code)?;
+        let raw = read_value(&self.path)?.min(self.max);
+        match self.last {
+            Some((last_raw, value)) if last_raw == raw => Ok(value),
+            _ => Ok((raw as f64 * 100.0 / self.max as f64).round() as u16),
+        }
+    }
+
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        check_code(code)?;
+        let value = value.min(100);
+        let raw = (value as f64 * self.max as f64 / 100.0).round() as u32;
+        self.last = None;
+        fs::write(&self.path, raw.to_string())
+            .with_context(|| format!("Failed to write {raw} to {0:?}", self.path))?;
+        self.last = Some((raw, value));
+        Ok(())
+    }
+}
+
+fn check_code(code: u8) -> Result<(), anyhow::Error> {
+    if code != BRIGHTNESS {
+        anyhow::bail!("Backlight devices only support brightness, not VCP feature {code:#04x}");
+    }
+    Ok(())
+}
+
+fn read_value(path: &Path) -> Result<u32, anyhow::Error> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    contents
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid value in {path:?}: {contents:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// Fake `/sys/class/backlight` with the given (name, max_brightness, brightness) devices.
+    fn fake_sysfs(devices: &[(&str, u32, u32)]) -> TempDir {
+        let dir = TempDir::new("backlight");
+        for (name, max, brightness) in devices {
+            let dev = dir.join(name);
+            fs::create_dir_all(&dev).unwrap();
+            fs::write(dev.join("max_brightness"), format!("{max}\n")).unwrap();
+            fs::write(dev.join("brightness"), format!("{brightness}\n")).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn detect() {
+        let dir = fake_sysfs(&[("nvidia_0", 100, 50), ("intel_backlight", 1000, 0)]);
+        let devices = BacklightDevice::detect(&dir).unwrap();
+        let names: Vec<_> = devices.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(vec!["intel_backlight", "nvidia_0"], names);
+
+        assert!(
+            BacklightDevice::detect(dir.join("missing"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn scaling() {
+        let dir = fake_sysfs(&[("intel_backlight", 19393, 9697)]);
+        let device = &BacklightDevice::detect(&dir).unwrap()[0];
+        let mut backlight = device.open().unwrap();
+
+        assert_eq!(50, backlight.get(BRIGHTNESS).unwrap());
+
+        backlight.set(BRIGHTNESS, 25).unwrap();
+        let raw = fs::read_to_string(device.path.join("brightness")).unwrap();
+        assert_eq!("4848", raw);
+        assert_eq!(25, backlight.get(BRIGHTNESS).unwrap());
+
+        backlight.set(BRIGHTNESS, 100).unwrap();
+        assert_eq!(100, backlight.get(BRIGHTNESS).unwrap());
+
+        // Only brightness is supported
+        assert!(backlight.get(0x12).is_err());
+        assert!(backlight.set(0x12, 50).is_err());
+    }
+
+    #[test]
+    fn small_max_brightness() {
+        let dir = fake_sysfs(&[("acpi_video0", 10, 3)]);
+        let device = &BacklightDevice::detect(&dir).unwrap()[0];
+        let mut backlight = device.open().unwrap();
+        assert_eq!(30, backlight.get(BRIGHTNESS).unwrap());
+
+        // 55% is written as 6, which reads back as the 55% that was set, not 60%
+        backlight.set(BRIGHTNESS, 55).unwrap();
+        let raw = fs::read_to_string(device.path.join("brightness")).unwrap();
+        assert_eq!("6", raw);
+        assert_eq!(55, backlight.get(BRIGHTNESS).unwrap());
+
+        // Changed from elsewhere, e.g. with the brightness keys
+        fs::write(device.path.join("brightness"), "8\n").unwrap();
+        assert_eq!(80, backlight.get(BRIGHTNESS).unwrap());
+    }
+}
@@ -1,46 +1,693 @@
+use crate::glob::glob_match;
+use crate::monitor::{BRIGHTNESS, CONTRAST};
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub enum MonitorId {
-    // TODO USB device, hiddev
     Default,
     I2cBus(u32),
+    Connector(String),     // DRM connector, e.g. "DP-2"
     Model(String, String), // manufacturer, model
     Serial(String),
     ModelSerial(String, String, String), // manufacturer, model, serial#
+    Backlight(String),                   // device name under /sys/class/backlight
+    Software(String),                    // display label of the compositor's brightness service
+    EdidHash(String),                    // hash of the EDID, as shown by `check`
+    EdidSerial(u32),                     // binary serial number from the EDID
+    Usb(u16, u16),                       // vendor ID, product ID of a USB monitor
+    ModelGlob(String, String),           // manufacturer, model, as glob patterns
+    SerialGlob(String),                  // serial# as a glob pattern
+    All(Vec<MonitorId>),                 // all of the identifiers apply
+    Any(Vec<MonitorId>),                 // at least one of the identifiers applies
+}
+
+impl MonitorId {
+    /// Lower values are more specific and take precedence when multiple identifiers apply.
+    fn priority(&self) -> u32 {
+        match self {
+            MonitorId::I2cBus(_)
+            | MonitorId::Connector(_)
+            | MonitorId::Backlight(_)
+            | MonitorId::Software(_) => 0,
+            MonitorId::EdidHash(_) => 5,
+            MonitorId::ModelSerial(_, _, _) => 10,
+            MonitorId::Serial(_) | MonitorId::EdidSerial(_) => 11,
+            MonitorId::Model(_, _) | MonitorId::Usb(_, _) => 20,
+            MonitorId::SerialGlob(_) => 21,
+            MonitorId::ModelGlob(_, _) => 25,
+            // as specific as the most specific part, or the least specific part of alternatives
+            MonitorId::All(ids) => ids.iter().map(Self::priority).min().unwrap_or(100),
+            MonitorId::Any(ids) => ids.iter().map(Self::priority).max().unwrap_or(100),
+            MonitorId::Default => 100,
+        }
+    }
+
+    /// Whether this only applies to backlights or software displays, which only support brightness.
+    fn brightness_only(&self) -> bool {
+        match self {
+            MonitorId::Backlight(_) | MonitorId::Software(_) => true,
+            MonitorId::All(ids) => ids.iter().any(Self::brightness_only),
+            MonitorId::Any(ids) => !ids.is_empty() && ids.iter().all(Self::brightness_only),
+            _ => false,
+        }
+    }
+
+    /// Whether an `All` or `Any` in this identifier has no identifiers in it.
+    fn has_empty_combination(&self) -> bool {
+        match self {
+            MonitorId::All(ids) | MonitorId::Any(ids) => {
+                ids.is_empty() || ids.iter().any(Self::has_empty_combination)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether every display this identifier applies to is certainly also matched by `self`. This
+    /// only recognizes simple cases, such as a glob pattern covering a literal name.
+    fn covers(&self, other: &MonitorId) -> bool {
+        let literal = |s: &str| !s.contains(['*', '?']);
+        match (self, other) {
+            (a, b) if a == b => true,
+            (_, MonitorId::Any(ids)) => !ids.is_empty() && ids.iter().all(|id| self.covers(id)),
+            (_, MonitorId::All(ids)) => ids.iter().any(|id| self.covers(id)),
+            (MonitorId::Any(ids), _) => ids.iter().any(|id| id.covers(other)),
+            (
+                MonitorId::ModelGlob(mfg, model),
+                MonitorId::Model(other_mfg, other_model)
+                | MonitorId::ModelGlob(other_mfg, other_model),
+            ) => {
+                literal(other_mfg)
+                    && literal(other_model)
+                    && glob_match(mfg, other_mfg)
+                    && glob_match(model, other_model)
+            }
+            (MonitorId::Model(mfg, model), MonitorId::ModelGlob(other_mfg, other_model)) => {
+                literal(other_mfg)
+                    && literal(other_model)
+                    && mfg == other_mfg
+                    && model == other_model
+            }
+            (
+                MonitorId::SerialGlob(serial),
+                MonitorId::Serial(other) | MonitorId::SerialGlob(other),
+            ) => literal(other) && glob_match(serial, other),
+            (MonitorId::Serial(serial), MonitorId::SerialGlob(other)) => {
+                literal(other) && serial == other
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct MonitorConfig {
     pub identifier: MonitorId,
-    pub curve: Vec<(u32, u32)>,
+    /// Brightness curve, or the name of one of the top-level `curves`. Values below 0 are only
+    /// allowed with `extended_dimming`. With `luminance`, the values are the target luminance in
+    /// nits instead
+    pub curve: Curve,
+    /// Optional contrast curve, evaluated from lux the same way as the brightness curve
+    #[serde(default)]
+    pub contrast: Option<Vec<(f64, u32)>>,
+    /// Additional VCP features to control, as (VCP code, curve) pairs
+    #[serde(default)]
+    pub features: Vec<(u8, Vec<(f64, u32)>)>,
+    /// VCP features to switch between a day and a night value, as (VCP code, day value, night value)
+    #[serde(default)]
+    pub night: Vec<(u8, u16, u16)>,
+    /// Learn from manual brightness adjustments, adapting the curve to match
+    #[serde(default)]
+    pub learn: bool,
+    /// How to interpolate between the points of this monitor's curves
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// How to interpolate individual curves instead, by VCP code (e.g. 0x12 for contrast)
+    #[serde(default)]
+    pub interpolations: BTreeMap<u8, Interpolation>,
+    /// How to move between brightness values
+    #[serde(default)]
+    pub transition: TransitionConfig,
+    /// When to change the brightness target
+    #[serde(default)]
+    pub hysteresis: Hysteresis,
+    /// How to handle unreliable DDC/CI writes
+    #[serde(default)]
+    pub write: WritePolicy,
+    /// Refuse to run unless a display matching this configuration is detected
+    #[serde(default)]
+    pub required: bool,
+    /// Keep dimming in software once the hardware brightness reaches 0
+    #[serde(default)]
+    pub extended_dimming: Option<ExtendedDimming>,
+    /// Lowest brightness to set, after applying the offset
+    #[serde(default)]
+    pub min: Option<i32>,
+    /// Highest brightness to set, after applying the offset
+    #[serde(default)]
+    pub max: Option<i32>,
+    /// Added to the brightness from the curve, to make the monitor brighter or darker overall
+    #[serde(default)]
+    pub offset: i32,
+    /// Measured luminance of the monitor, as (brightness, nits) pairs. If set, the curve is in nits
+    /// and converted to this monitor's brightness, so monitors with different peak luminance match
+    #[serde(default)]
+    pub luminance: Option<Vec<(i32, u32)>>,
+    /// Name of the top-level curve that `curve` was resolved from, if any
+    #[serde(skip)]
+    pub preset: Option<String>,
+}
+
+/// A brightness curve given in place, or by name.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Curve {
+    /// (lux, brightness) points
+    Points(Vec<(f64, i32)>),
+    /// Name of one of the top-level `curves`, replaced by its points when the config is loaded
+    Preset(String),
+}
+
+// Not derived, so mistakes in the points are reported where they are, rather than as not matching
+// either kind of curve
+impl<'de> Deserialize<'de> for Curve {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CurveVisitor;
+
+        impl<'de> Visitor<'de> for CurveVisitor {
+            type Value = Curve;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of (lux, brightness) points, or the name of a curve")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Curve, E> {
+                Ok(Curve::Preset(name.to_string()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Curve, A::Error> {
+                Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq))
+                    .map(Curve::Points)
+            }
+        }
+
+        deserializer.deserialize_any(CurveVisitor)
+    }
+}
+
+impl MonitorConfig {
+    /// Points of the brightness curve. Empty if it refers to a curve that doesn't exist.
+    pub fn curve_points(&self) -> &[(f64, i32)] {
+        match &self.curve {
+            Curve::Points(points) => points,
+            Curve::Preset(_) => &[],
+        }
+    }
+
+    /// How far `brightness_curve` is shifted up from the configured curve.
+    pub fn curve_shift(&self) -> i32 {
+        match self.luminance {
+            Some(_) => 0,
+            None => self.extended_dimming.as_ref().map_or(0, |e| e.range as i32),
+        }
+    }
+
+    /// The brightness curve, shifted up by the extended dimming range so all values are positive.
+    /// Curves in nits aren't shifted.
+    pub fn brightness_curve(&self) -> Vec<(f64, u32)> {
+        let range = self.curve_shift();
+        self.curve_points()
+            .iter()
+            .map(|&(lux, b)| (lux, (b + range).max(0) as u32))
+            .collect()
+    }
+
+    /// How to interpolate the curve of the VCP feature with the given code.
+    pub fn interpolation_of(&self, code: u8) -> Interpolation {
+        self.interpolations
+            .get(&code)
+            .copied()
+            .unwrap_or(self.interpolation)
+    }
+
+    /// The `min` and `max` brightness, shifted the same way as `brightness_curve`.
+    pub fn brightness_limits(&self) -> (Option<u16>, Option<u16>) {
+        let range = self.extended_dimming.as_ref().map_or(0, |e| e.range as i32);
+        let shift =
+            |limit: Option<i32>| limit.map(|l| (l + range).clamp(0, u16::MAX as i32) as u16);
+        (shift(self.min), shift(self.max))
+    }
+
+    /// Check the monitor's settings, adding any problems to `errors`, and anything that is likely
+    /// a mistake to `warnings`.
+    fn validate(&self, errors: &mut Vec<String>, warnings: &mut Vec<String>) {
+        let id = &self.identifier;
+        // Lowest brightness the monitor can be set to
+        let lowest = self
+            .extended_dimming
+            .as_ref()
+            .map_or(0, |e| -(e.range as i32));
+        if let Some(e) = &self.extended_dimming
+            && e.range > 100
+        {
+            errors.push(format!(
+                "Extended dimming range of monitor {id:?} is more than 100"
+            ));
+        }
+        if let Some(profile) = &self.luminance {
+            let mut sorted = profile.clone();
+            sorted.sort();
+            if sorted.is_empty()
+                || sorted[0].0 < lowest
+                || sorted
+                    .windows(2)
+                    .any(|w| w[0].0 >= w[1].0 || w[0].1 >= w[1].1)
+            {
+                errors.push(format!(
+                    "Luminance profile of monitor {id:?} must have brightness values of at least {lowest}, and luminance increasing with brightness"
+                ));
+            }
+        }
+
+        // Missing presets are reported when resolving them
+        let curve = self.curve_points();
+        if let Curve::Points(_) = self.curve {
+            check_curve(
+                curve,
+                &format!("Brightness curve of monitor {id:?}"),
+                errors,
+            );
+        }
+        // Curves in nits can't be negative, and aren't limited to 100
+        let (min, max) = match self.luminance {
+            Some(_) => (0, i32::MAX),
+            None => (lowest, 100),
+        };
+        for &(lux, b) in curve {
+            if b < min {
+                errors.push(format!(
+                    "Brightness {b} at {lux} lux of monitor {id:?} is below the minimum of {min}"
+                ));
+            } else if b > max {
+                errors.push(format!(
+                    "Brightness {b} at {lux} lux of monitor {id:?} is above {max}"
+                ));
+            }
+        }
+        let mut sorted = curve.to_vec();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(w) = sorted.windows(2).find(|w| w[0].1 > w[1].1) {
+            warnings.push(format!(
+                "Brightness curve of monitor {id:?} decreases from {0} at {1} lux to {2} at {3} lux",
+                w[0].1, w[0].0, w[1].1, w[1].0
+            ));
+        }
+
+        if let Some(contrast) = &self.contrast {
+            check_curve(
+                contrast,
+                &format!("Contrast curve of monitor {id:?}"),
+                errors,
+            );
+            if let Some(&(lux, c)) = contrast.iter().find(|&&(_, c)| c > 100) {
+                errors.push(format!(
+                    "Contrast {c} at {lux} lux of monitor {id:?} is above 100"
+                ));
+            }
+        }
+        for (code, curve) in &self.features {
+            let what = format!("Curve of VCP feature {code:#04x} of monitor {id:?}");
+            check_curve(curve, &what, errors);
+        }
+
+        if let Interpolation::Gamma(gamma) = self.interpolation
+            && !valid_gamma(gamma)
+        {
+            errors.push(format!(
+                "Interpolation gamma {gamma} of monitor {id:?} must be above 0"
+            ));
+        }
+        for (&code, &interpolation) in &self.interpolations {
+            if code != BRIGHTNESS
+                && !(code == CONTRAST && self.contrast.is_some())
+                && !self.features.iter().any(|&(c, _)| c == code)
+            {
+                errors.push(format!(
+                    "Interpolation is set for VCP feature {code:#04x} of monitor {id:?}, which has no curve"
+                ));
+            }
+            if let Interpolation::Gamma(gamma) = interpolation
+                && !valid_gamma(gamma)
+            {
+                errors.push(format!(
+                    "Interpolation gamma {gamma} of VCP feature {code:#04x} of monitor {id:?} must be above 0"
+                ));
+            }
+        }
+
+        if id.brightness_only()
+            && (self.contrast.is_some() || !self.features.is_empty() || !self.night.is_empty())
+        {
+            errors.push(format!(
+                "Monitor {id:?} only supports brightness, but has contrast, features or night features"
+            ));
+        }
+
+        if self.write.max_consecutive_failures == 0 {
+            errors.push(format!(
+                "Maximum consecutive write failures of monitor {id:?} must be more than 0"
+            ));
+        }
+        if self.transition.interval_ms == 0 {
+            errors.push(format!(
+                "Transition interval of monitor {id:?} must be more than 0 ms"
+            ));
+        }
+        for (direction, speed) in [
+            ("brighten", &self.transition.brighten),
+            ("dim", &self.transition.dim),
+        ] {
+            if let Easing::Gamma(gamma) = speed.easing
+                && !valid_gamma(gamma)
+            {
+                errors.push(format!(
+                    "Gamma {gamma} of {direction} transitions of monitor {id:?} must be above 0"
+                ));
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min, self.max)
+            && min > max
+        {
+            errors.push(format!(
+                "Minimum brightness {min} of monitor {id:?} is above the maximum of {max}"
+            ));
+        }
+        if let Some(min) = self.min {
+            if min > 100 {
+                errors.push(format!(
+                    "Minimum brightness {min} of monitor {id:?} is above 100"
+                ));
+            } else if min < lowest {
+                errors.push(format!(
+                    "Minimum brightness {min} of monitor {id:?} is below the minimum of {lowest}"
+                ));
+            }
+        }
+        if let Some(max) = self.max
+            && max < lowest
+        {
+            errors.push(format!(
+                "Maximum brightness {max} of monitor {id:?} is below the minimum of {lowest}"
+            ));
+        }
+    }
+
+    /// The luminance profile, with brightness shifted the same way as `brightness_curve`.
+    pub fn luminance_profile(&self) -> Option<Vec<(u32, u32)>> {
+        let range = self.extended_dimming.as_ref().map_or(0, |e| e.range as i32);
+        let profile = self.luminance.as_ref()?;
+        Some(
+            profile
+                .iter()
+                .map(|&(b, nits)| ((b + range).max(0) as u32, nits))
+                .collect(),
+        )
+    }
+}
+
+/// Whether a gamma can be used to convert between perceived and linear brightness.
+fn valid_gamma(gamma: f64) -> bool {
+    gamma.is_finite() && gamma > 0.0
+}
+
+/// Check that a curve has points, and that its lux values are valid and unique. `what` names the
+/// curve in error messages.
+fn check_curve<Y>(curve: &[(f64, Y)], what: &str, errors: &mut Vec<String>) {
+    if curve.is_empty() {
+        errors.push(format!("{what} has no points"));
+    }
+    if let Some(&(lux, _)) = curve.iter().find(|&&(lux, _)| lux.is_nan() || lux < 0.0) {
+        errors.push(format!("{what} has an invalid lux value {lux}"));
+    }
+    let mut lux: Vec<f64> = curve.iter().map(|&(lux, _)| lux).collect();
+    lux.sort_by(f64::total_cmp);
+    if let Some(w) = lux.windows(2).find(|w| w[0] == w[1]) {
+        errors.push(format!("{what} has more than one point at {0} lux", w[0]));
+    }
+}
+
+/// Extends the brightness range below 0% by dimming in software once the monitor's own brightness
+/// reaches 0.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ExtendedDimming {
+    /// Label of the software display (as in `MonitorId::Software`) of the same monitor
+    pub software: String,
+    /// How far below 0% the brightness can go. Each percent below 0 dims the software brightness by
+    /// one percent.
+    pub range: u16,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct WritePolicy {
+    /// Read each value back after writing it, and retry if it doesn't match
+    pub verify: bool,
+    /// Number of times to retry a failed write
+    pub retries: u32,
+    /// Delay before the first retry in milliseconds, doubling for each subsequent retry
+    pub backoff_ms: u64,
+    /// Give up after this many consecutive failed writes
+    pub max_consecutive_failures: u32,
+}
+
+impl Default for WritePolicy {
+    fn default() -> Self {
+        WritePolicy {
+            verify: false,
+            retries: 2,
+            backoff_ms: 50,
+            max_consecutive_failures: 10,
+        }
+    }
+}
+
+/// How a curve is interpolated between its points.
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum Interpolation {
+    /// Straight lines between the points
+    #[default]
+    Linear,
+    /// Linear in the logarithm of lux, which is closer to how light levels are perceived. Points
+    /// can then be spread out evenly in orders of magnitude, e.g. 1, 10, 100, 1000 lux.
+    LogLux,
+    /// Linear in perceived brightness, using the given gamma (e.g. 2.2)
+    Gamma(f64),
+    /// Smooth monotone cubic spline through the points (Fritsch–Carlson), without the kinks of
+    /// straight lines. It never overshoots: between two points, it stays between their values.
+    MonotoneCubic,
+}
+
+/// Policy to avoid changing the target too often when lux fluctuates.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum Hysteresis {
+    /// Round the target towards the current value, to a multiple of `step`, or of `low_step` for
+    /// targets of `low_threshold` and below
+    Round {
+        step: u16,
+        low_step: u16,
+        low_threshold: u16,
+    },
+    /// Only change the target once lux has changed by more than this percentage since the last change
+    LuxDeadband(f64),
+    /// Minimum time between target changes, in milliseconds
+    MinInterval(u64),
+}
+
+impl Default for Hysteresis {
+    fn default() -> Self {
+        Hysteresis::Round {
+            step: 5,
+            low_step: 2,
+            low_threshold: 20,
+        }
+    }
+}
+
+/// Shape of a transition between two values
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum Easing {
+    /// Constant speed
+    Linear,
+    /// Fast at first, slowing down towards the target
+    EaseOut,
+    /// Linear in perceived brightness, using the given gamma (e.g. 2.2)
+    Gamma(f64),
+}
+
+/// Speed and shape of transitions in one direction
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TransitionSpeed {
+    /// Total duration of a transition in milliseconds, regardless of distance. If not set, the
+    /// transition moves by `step` every interval instead.
+    pub duration_ms: Option<u64>,
+    /// Change per interval, if `duration_ms` is not set
+    pub step: u16,
+    pub easing: Easing,
+}
+
+impl Default for TransitionSpeed {
+    fn default() -> Self {
+        TransitionSpeed {
+            duration_ms: None,
+            step: 2,
+            easing: Easing::Linear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TransitionConfig {
+    /// Time between updates while a transition is in progress, in milliseconds
+    pub interval_ms: u64,
+    /// Jump straight to the target once within this distance of it
+    pub snap: u16,
+    /// Transitions to a higher value
+    pub brighten: TransitionSpeed,
+    /// Transitions to a lower value
+    pub dim: TransitionSpeed,
+}
+
+impl Default for TransitionConfig {
+    fn default() -> Self {
+        TransitionConfig {
+            interval_ms: 100,
+            snap: 3,
+            brighten: TransitionSpeed::default(),
+            dim: TransitionSpeed::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub enum NightSchedule {
+    /// Night between two local times, as (hour, minute)
+    Fixed((u8, u8), (u8, u8)),
+    /// Night between sunset and sunrise at the given latitude and longitude (degrees, east positive)
+    Sun(f64, f64),
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct Config {
     pub monitors: Vec<MonitorConfig>,
+    /// Named brightness curves that monitors can refer to, to share a curve between them
+    #[serde(default)]
+    pub curves: BTreeMap<String, Vec<(f64, i32)>>,
+    /// When to switch monitors' night features to their night values
+    #[serde(default)]
+    pub night: Option<NightSchedule>,
+    /// How often to check for displays being connected or disconnected, in seconds. 0 to disable.
+    #[serde(default = "default_hotplug_interval")]
+    pub hotplug_interval_secs: u64,
+    /// How long to wait at startup for required monitors to be detected, in seconds
+    #[serde(default)]
+    pub required_timeout_secs: u64,
     // TODO: could configure brightness sensor (different intermediate chips (vid,pid), maybe implement different sensors)
 }
 
+pub fn default_hotplug_interval() -> u64 {
+    60
+}
+
 impl Config {
     fn validate_and_normalize(mut self) -> Result<Self, anyhow::Error> {
         // Sort by priority. Sorting is stable, so position is the tie-breaker if multiple categories apply
-        self.monitors.sort_by_key(|m| match m.identifier {
-            MonitorId::I2cBus(_) => 0,
-            MonitorId::ModelSerial(_, _, _) => 10,
-            MonitorId::Serial(_) => 11,
-            MonitorId::Model(_, _) => 20,
-            MonitorId::Default => 100,
-        });
+        self.monitors.sort_by_key(|m| m.identifier.priority());
+
+        // Collect every problem, so they can all be fixed at once
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        // Replace references to named curves by the curves themselves
+        for m in &mut self.monitors {
+            let Curve::Preset(name) = &m.curve else {
+                continue;
+            };
+            match self.curves.get(name) {
+                Some(points) => {
+                    m.preset = Some(name.clone());
+                    m.curve = Curve::Points(points.clone());
+                }
+                None => errors.push(format!(
+                    "Monitor {0:?} uses the curve {name:?}, which isn't defined in `curves`",
+                    m.identifier
+                )),
+            }
+        }
+        for name in self.curves.keys() {
+            if !self
+                .monitors
+                .iter()
+                .any(|m| m.preset.as_ref() == Some(name))
+            {
+                warnings.push(format!("Curve {name:?} isn't used by any monitor"));
+            }
+        }
 
-        // TODO validation?
-        // - only one default
-        // - in general no duplicates
-        // - validate curves?
+        if self.night.is_none() && self.monitors.iter().any(|m| !m.night.is_empty()) {
+            errors.push(
+                "Night mode features are configured, but there is no night schedule".to_string(),
+            );
+        }
+        for m in &self.monitors {
+            m.validate(&mut errors, &mut warnings);
+        }
+        for m in &self.monitors {
+            if m.identifier.has_empty_combination() {
+                errors.push(format!(
+                    "Monitor {0:?} combines no identifiers in All or Any",
+                    m.identifier
+                ));
+            }
+        }
+
+        // Later identifiers that can only match displays an earlier one already matches never apply
+        for (i, m) in self.monitors.iter().enumerate() {
+            let id = &m.identifier;
+            let Some(earlier) = self.monitors[..i]
+                .iter()
+                .map(|e| &e.identifier)
+                .find(|e| e.covers(id))
+            else {
+                continue;
+            };
+            errors.push(match id {
+                MonitorId::Default => "More than one Default monitor is configured".to_string(),
+                _ if earlier == id => format!("Monitor {id:?} is configured more than once"),
+                _ => format!("Monitor {id:?} never applies, because {earlier:?} comes first"),
+            });
+        }
+
+        if let Some(NightSchedule::Fixed(start, end)) = self.night
+            && (start.0 >= 24 || start.1 >= 60 || end.0 >= 24 || end.1 >= 60)
+        {
+            errors.push(format!("Invalid night schedule time: {start:?} - {end:?}"));
+        }
 
+        for warning in &warnings {
+            eprintln!("Warning: {warning}");
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  - {0}", errors.join("\n  - "));
+        }
         Ok(self)
     }
 
@@ -67,18 +714,59 @@ mod test {
         monitors: [
             (
                 identifier: Model("abc", "xyz"),
-                curve: [
-                    (0, 10),
-                    (250, 100),
-                ],
+                curve: "office",
             ),
             (
                 identifier: I2cBus(6),
                 curve: [
-                    (0, 50),
+                    (0.5, -20),
+                    (50, 50),
                 ],
+                contrast: Some([
+                    (0, 40),
+                    (100, 70),
+                ]),
+                features: [
+                    (0x16, [(0, 80), (200, 100)]),
+                ],
+                night: [
+                    (0x14, 6, 4),
+                ],
+                learn: true,
+                interpolation: LogLux,
+                interpolations: {
+                    0x12: Gamma(2.2),
+                },
+                transition: (
+                    interval_ms: 50,
+                    dim: (
+                        duration_ms: Some(5000),
+                        easing: Gamma(2.2),
+                    ),
+                ),
+                hysteresis: LuxDeadband(15.0),
+                write: (
+                    verify: true,
+                    retries: 5,
+                ),
+                required: true,
+                extended_dimming: Some((
+                    software: "LG HDR 4K",
+                    range: 30,
+                )),
+                min: Some(-10),
+                max: Some(90),
+                offset: 5,
             ),
-        ]
+        ],
+        curves: {
+            "office": [
+                (0, 10),
+                (250, 100),
+            ],
+        },
+        night: Some(Sun(43.65, -79.38)),
+        required_timeout_secs: 30,
         )
     "#;
 
@@ -92,14 +780,273 @@ mod test {
                 monitors: vec![
                     MonitorConfig {
                         identifier: MonitorId::Model("abc".to_string(), "xyz".to_string()),
-                        curve: vec![(0, 10), (250, 100)],
+                        curve: Curve::Preset("office".to_string()),
+                        contrast: None,
+                        features: vec![],
+                        night: vec![],
+                        learn: false,
+                        interpolation: Interpolation::Linear,
+                        interpolations: BTreeMap::new(),
+                        transition: TransitionConfig::default(),
+                        hysteresis: Hysteresis::default(),
+                        write: WritePolicy::default(),
+                        required: false,
+                        extended_dimming: None,
+                        min: None,
+                        max: None,
+                        offset: 0,
+                        luminance: None,
+                        preset: None,
                     },
                     MonitorConfig {
                         identifier: MonitorId::I2cBus(6),
-                        curve: vec![(0, 50)],
+                        curve: Curve::Points(vec![(0.5, -20), (50.0, 50)]),
+                        contrast: Some(vec![(0.0, 40), (100.0, 70)]),
+                        features: vec![(0x16, vec![(0.0, 80), (200.0, 100)])],
+                        night: vec![(0x14, 6, 4)],
+                        learn: true,
+                        interpolation: Interpolation::LogLux,
+                        interpolations: BTreeMap::from([(0x12, Interpolation::Gamma(2.2))]),
+                        transition: TransitionConfig {
+                            interval_ms: 50,
+                            dim: TransitionSpeed {
+                                duration_ms: Some(5000),
+                                step: 2,
+                                easing: Easing::Gamma(2.2),
+                            },
+                            ..Default::default()
+                        },
+                        hysteresis: Hysteresis::LuxDeadband(15.0),
+                        write: WritePolicy {
+                            verify: true,
+                            retries: 5,
+                            ..Default::default()
+                        },
+                        required: true,
+                        extended_dimming: Some(ExtendedDimming {
+                            software: "LG HDR 4K".to_string(),
+                            range: 30,
+                        }),
+                        min: Some(-10),
+                        max: Some(90),
+                        offset: 5,
+                        luminance: None,
+                        preset: None,
                     },
-                ]
+                ],
+                curves: BTreeMap::from([("office".to_string(), vec![(0.0, 10), (250.0, 100)])]),
+                night: Some(NightSchedule::Sun(43.65, -79.38)),
+                hotplug_interval_secs: 60,
+                required_timeout_secs: 30,
             }
         );
     }
+
+    #[test]
+    fn test_identifier_priority() {
+        let config = Config::from_str(
+            r#"(monitors: [
+                (identifier: Default, curve: [(0, 1)]),
+                (identifier: Any([Model("GBT", "G27Q"), ModelGlob("GBT", "*")]), curve: [(0, 2)]),
+                (identifier: ModelGlob("GBT", "G27Q*"), curve: [(0, 3)]),
+                (identifier: All([SerialGlob("2323*"), Model("GBT", "G27Q")]), curve: [(0, 4)]),
+                (identifier: I2cBus(6), curve: [(0, 5)]),
+            ])"#,
+        )
+        .unwrap();
+        let order: Vec<_> = config
+            .monitors
+            .iter()
+            .map(|m| m.curve_points()[0].1)
+            .collect();
+        assert_eq!(vec![5, 4, 2, 3, 1], order);
+    }
+
+    #[test]
+    fn test_extended_dimming() {
+        let config = Config::from_str(TEST_CONFIG).unwrap();
+        assert_eq!(
+            vec![(0.5, 10), (50.0, 80)],
+            config.monitors[0].brightness_curve()
+        );
+        assert_eq!(
+            vec![(0.0, 10), (250.0, 100)],
+            config.monitors[1].brightness_curve()
+        );
+
+        // Brightness can only go below 0 with extended dimming, and only as far as its range
+        let config = |curve, dimming| {
+            Config::from_str(&format!(
+                "(monitors: [(identifier: Default, curve: {curve}, extended_dimming: {dimming})])"
+            ))
+        };
+        assert!(config("[(0, -1), (100, 50)]", "None").is_err());
+        assert!(
+            config(
+                "[(0, -30), (100, 50)]",
+                "Some((software: \"x\", range: 30))"
+            )
+            .is_ok()
+        );
+        assert!(
+            config(
+                "[(0, -31), (100, 50)]",
+                "Some((software: \"x\", range: 30))"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_brightness_limits() {
+        // Limits are shifted by the extended dimming range, like the curve
+        let config = Config::from_str(TEST_CONFIG).unwrap();
+        assert_eq!(
+            (Some(20), Some(120)),
+            config.monitors[0].brightness_limits()
+        );
+        assert_eq!((None, None), config.monitors[1].brightness_limits());
+
+        let config = |min, max| {
+            Config::from_str(&format!(
+                "(monitors: [(identifier: Default, curve: [(0, 10)], min: {min}, max: {max})])"
+            ))
+        };
+        assert!(config("Some(20)", "Some(80)").is_ok());
+        assert!(config("Some(20)", "None").is_ok());
+        assert!(config("Some(80)", "Some(20)").is_err());
+        assert!(config("Some(150)", "None").is_err());
+        assert!(config("None", "Some(-5)").is_err());
+        assert!(config("Some(-5)", "None").is_err());
+    }
+
+    #[test]
+    fn test_luminance() {
+        let config = |curve, luminance, dimming| {
+            Config::from_str(&format!(
+                "(monitors: [(identifier: Default, curve: {curve}, luminance: {luminance}, extended_dimming: {dimming})])"
+            ))
+        };
+
+        // With extended dimming, the profile is shifted but the curve in nits isn't
+        let dimming = "Some((software: \"x\", range: 30))";
+        let monitor = &config(
+            "[(0, 20), (250, 200)]",
+            "Some([(-30, 5), (100, 300)])",
+            dimming,
+        )
+        .unwrap()
+        .monitors[0];
+        assert_eq!(vec![(0.0, 20), (250.0, 200)], monitor.brightness_curve());
+        assert_eq!(Some(vec![(0, 5), (130, 300)]), monitor.luminance_profile());
+
+        assert!(config("[(0, -5)]", "Some([(0, 5), (100, 300)])", dimming).is_err());
+        assert!(config("[(0, 20)]", "Some([(-40, 5), (100, 300)])", dimming).is_err());
+        assert!(config("[(0, 20)]", "Some([(0, 50), (100, 50)])", "None").is_err());
+        assert!(config("[(0, 20)]", "Some([])", "None").is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        // All problems are reported at once
+        let err = Config::from_str(
+            r#"(monitors: [
+                (identifier: Default, curve: [(0, 10), (0, 20)]),
+                (identifier: Default, curve: [(0, 10)]),
+                (identifier: Model("GBT", "G27Q"), curve: [(0, 120)]),
+                (identifier: Model("GBT", "G27Q"), curve: [(0, 10)]),
+                (identifier: ModelGlob("GBT", "G27Q"), curve: [(0, 10)]),
+                (identifier: ModelGlob("GBT", "*"), curve: [(0, 10)]),
+                (identifier: SerialGlob("2323*"), curve: [(-1, 10)], contrast: Some([])),
+                (identifier: Serial("23231234"), curve: [(0, 10)]),
+                (identifier: All([Serial("1"), Any([])]), curve: [(0, 10)]),
+                (identifier: Backlight("intel_backlight"), curve: [(0, 10)], contrast: Some([(0, 50)])),
+            ])"#,
+        )
+        .unwrap_err()
+        .to_string();
+        for problem in [
+            "Brightness curve of monitor Default has more than one point at 0 lux",
+            "More than one Default monitor is configured",
+            "Brightness 120 at 0 lux of monitor Model(\"GBT\", \"G27Q\") is above 100",
+            "Monitor Model(\"GBT\", \"G27Q\") is configured more than once",
+            "Monitor ModelGlob(\"GBT\", \"G27Q\") never applies, because Model(\"GBT\", \"G27Q\") comes first",
+            "Brightness curve of monitor SerialGlob(\"2323*\") has an invalid lux value -1",
+            "Contrast curve of monitor SerialGlob(\"2323*\") has no points",
+            "Monitor All([Serial(\"1\"), Any([])]) combines no identifiers in All or Any",
+            "Monitor Backlight(\"intel_backlight\") only supports brightness, but has contrast, features or night features",
+        ] {
+            assert!(err.contains(problem), "{problem:?} missing from {err}");
+        }
+        assert_eq!(9, err.lines().count() - 1);
+
+        // A decreasing curve is only a warning, and more specific identifiers can come later
+        assert!(
+            Config::from_str(
+                r#"(monitors: [
+                    (identifier: ModelGlob("GBT", "*"), curve: [(0, 50), (100, 20)]),
+                    (identifier: Model("GBT", "G27Q"), curve: [(0, 10)]),
+                    (identifier: Any([Model("GBT", "G27Q"), Serial("2323")]), curve: [(0, 10)]),
+                ])"#
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_transition_validation() {
+        let config = |transition| {
+            Config::from_str(&format!(
+                "(monitors: [(identifier: Default, curve: [(0, 10)], transition: {transition})])"
+            ))
+        };
+        assert!(config("(interval_ms: 50, dim: (easing: Gamma(2.2)))").is_ok());
+        assert!(config("(interval_ms: 0)").is_err());
+        assert!(config("(brighten: (easing: Gamma(0.0)))").is_err());
+        assert!(config("(dim: (easing: Gamma(-2.2)))").is_err());
+        assert!(config("(dim: (easing: Gamma(NaN)))").is_err());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let config = Config::from_str(TEST_CONFIG).unwrap();
+        let monitor = &config.monitors[0];
+        assert_eq!(Interpolation::LogLux, monitor.interpolation_of(BRIGHTNESS));
+        assert_eq!(
+            Interpolation::Gamma(2.2),
+            monitor.interpolation_of(CONTRAST)
+        );
+
+        let config = |interpolation, interpolations| {
+            Config::from_str(&format!(
+                "(monitors: [(identifier: Default, curve: [(0, 10)], contrast: Some([(0, 50)]), interpolation: {interpolation}, interpolations: {interpolations})])"
+            ))
+        };
+        assert!(config("Gamma(2.2)", "{0x10: Linear, 0x12: LogLux}").is_ok());
+        assert!(config("Gamma(0.0)", "{}").is_err());
+        assert!(config("Linear", "{0x12: Gamma(-1.0)}").is_err());
+        assert!(config("Linear", "{0x16: LogLux}").is_err());
+    }
+
+    #[test]
+    fn test_curve_presets() {
+        let config = Config::from_str(TEST_CONFIG).unwrap();
+        let monitor = &config.monitors[1];
+        assert_eq!(Some("office".to_string()), monitor.preset);
+        assert_eq!(Curve::Points(vec![(0.0, 10), (250.0, 100)]), monitor.curve);
+        assert_eq!(None, config.monitors[0].preset);
+
+        let err = Config::from_str(r#"(monitors: [(identifier: Default, curve: "dim")])"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("uses the curve \"dim\", which isn't defined"));
+
+        // Mistakes in curves are reported where they are
+        let err =
+            Config::from_str("(monitors: [(identifier: Default, curve: [(0, 10), (50, 2.5)])])")
+                .unwrap_err()
+                .to_string();
+        assert!(err.starts_with("1:58: "), "{err}");
+        assert!(Config::from_str("(monitors: [(identifier: Default, curve: 5)])").is_err());
+    }
 }
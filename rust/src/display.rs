@@ -0,0 +1,334 @@
+/// Detection of displays across all backends, and matching them to the configuration.
+use crate::backend::{Backend, Ddc};
+use crate::backlight::{BACKLIGHT_DIR, BacklightDevice};
+use crate::config::MonitorId;
+use crate::drm::{DRM_DIR, DrmConnector, edid_hash, edid_serial};
+use crate::glob::glob_match;
+use crate::hid::{DEV_DIR, HIDRAW_DIR, HidMonitor};
+use crate::software::SoftwareDisplay;
+
+use ddc::{self, ConvertToAnyhow};
+
+/// All displays that were detected.
+#[derive(Debug)]
+pub struct Displays {
+    ddc: ddc::DisplayInfoList,
+    connectors: Vec<DrmConnector>,
+    backlights: Vec<BacklightDevice>,
+    software: Vec<SoftwareDisplay>,
+    hid: Vec<HidMonitor>,
+}
+
+impl Displays {
+    pub fn detect() -> Result<Self, anyhow::Error> {
+        Ok(Displays {
+            // Wrap the error because DDC errors aren't sync so anyhow doesn't like them
+            ddc: ddc::get_display_info_list(false).anyhow()?,
+            connectors: DrmConnector::detect(DRM_DIR)?,
+            backlights: BacklightDevice::detect(BACKLIGHT_DIR)?,
+            // The compositor's service is optional, don't let it stop the other displays from working
+            software: SoftwareDisplay::detect().unwrap_or_else(|err| {
+                eprintln!("Failed to detect software brightness displays, ignoring them: {err:#}");
+                Vec::new()
+            }),
+            hid: HidMonitor::detect(HIDRAW_DIR, DEV_DIR)?,
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Display<'_>> {
+        self.ddc
+            .into_iter()
+            .map(|info| Display::Ddc {
+                info,
+                connector: self.connector(info),
+            })
+            .chain(self.backlights.iter().map(Display::Backlight))
+            .chain(self.software.iter().map(Display::Software))
+            .chain(self.hid.iter().map(Display::Hid))
+    }
+
+    /// The DRM connector of a DDC display: the one with the same I2C bus, or else the same EDID.
+    fn connector(&self, info: &ddc::DisplayInfo) -> Option<&DrmConnector> {
+        let by_bus = |c: &&DrmConnector| match info.path() {
+            ddc::DisplayPath::I2C { bus } => c.i2c_buses.iter().any(|&b| b as i32 == bus),
+            _ => false,
+        };
+        let by_edid = |c: &&DrmConnector| {
+            !info.edid_bytes().is_empty() && c.edid.starts_with(info.edid_bytes())
+        };
+        self.connectors
+            .iter()
+            .find(by_bus)
+            .or_else(|| self.connectors.iter().find(by_edid))
+    }
+
+    /// The software display with the given label, if any.
+    pub fn software(&self, label: &str) -> Option<Display<'_>> {
+        self.software
+            .iter()
+            .find(|s| s.label == label)
+            .map(Display::Software)
+    }
+}
+
+/// A single detected display.
+#[derive(Debug, Clone, Copy)]
+pub enum Display<'a> {
+    /// External monitor supporting DDC/CI, and the connector it is connected to if known
+    Ddc {
+        info: &'a ddc::DisplayInfo,
+        connector: Option<&'a DrmConnector>,
+    },
+    /// Internal panel with a sysfs backlight device
+    Backlight(&'a BacklightDevice),
+    /// Display dimmed by the compositor
+    Software(&'a SoftwareDisplay),
+    /// Monitor controlled over USB
+    Hid(&'a HidMonitor),
+}
+
+impl Display<'_> {
+    /// Name used to identify a display in logs and the learned curves file.
+    pub fn name(&self) -> String {
+        match self {
+            Display::Ddc { info: d, .. } => format!(
+                "{0} {1} {2}",
+                d.manufacturer(),
+                d.model(),
+                d.serial_number()
+            ),
+            Display::Backlight(b) => format!("backlight {0}", b.name),
+            Display::Software(s) => format!("software {0}", s.label),
+            Display::Hid(h) => format!("usb {0} {1}", h.name, h.serial),
+        }
+    }
+
+    /// Key of the display's saved state, such as learned curves and offsets. Monitors can have the
+    /// same name, e.g. with blank or duplicate serial numbers, so DDC monitors include the connector
+    /// they are connected to, or else their physical path.
+    pub fn state_key(&self) -> String {
+        match self {
+            Display::Ddc {
+                connector: Some(c), ..
+            } => format!("{0} {1}", self.name(), c.name),
+            Display::Ddc { .. } => format!("{0} {1}", self.name(), self.device()),
+            _ => self.name(),
+        }
+    }
+
+    /// Physical path of the display, to tell apart displays with the same name.
+    pub fn device(&self) -> String {
+        match self {
+            Display::Ddc { info: d, .. } => match d.path() {
+                ddc::DisplayPath::I2C { bus } => format!("i2c-{bus}"),
+                path => format!("{path:?}"),
+            },
+            Display::Backlight(b) => b.path.display().to_string(),
+            Display::Software(s) => s.name.clone(),
+            Display::Hid(h) => h.node.display().to_string(),
+        }
+    }
+
+    /// The most specific identifier for this display, e.g. to generate a configuration file.
+    pub fn identifier(&self) -> MonitorId {
+        match self {
+            Display::Ddc { info: d, .. } => MonitorId::ModelSerial(
+                d.manufacturer().to_string(),
+                d.model().to_string(),
+                d.serial_number().to_string(),
+            ),
+            Display::Backlight(b) => MonitorId::Backlight(b.name.clone()),
+            Display::Software(s) => MonitorId::Software(s.label.clone()),
+            Display::Hid(h) if h.serial.is_empty() => MonitorId::Usb(h.vendor_id, h.product_id),
+            Display::Hid(h) => MonitorId::All(vec![
+                MonitorId::Usb(h.vendor_id, h.product_id),
+                MonitorId::Serial(h.serial.clone()),
+            ]),
+        }
+    }
+
+    /// Values of every kind of identifier for this display (except `Default`).
+    pub fn identifiers(&self) -> Vec<MonitorId> {
+        match *self {
+            Display::Ddc { info: d, connector } => {
+                let mut ids = Vec::new();
+                if let ddc::DisplayPath::I2C { bus } = d.path() {
+                    ids.push(MonitorId::I2cBus(bus as u32));
+                }
+                if let Some(c) = connector {
+                    ids.push(MonitorId::Connector(c.name.clone()));
+                }
+                let (manufacturer, model, serial) = (
+                    d.manufacturer().to_string(),
+                    d.model().to_string(),
+                    d.serial_number().to_string(),
+                );
+                ids.push(MonitorId::Model(manufacturer.clone(), model.clone()));
+                ids.push(MonitorId::Serial(serial.clone()));
+                ids.push(MonitorId::ModelSerial(manufacturer, model, serial));
+                if let Some(hash) = edid_hash(self.edid()) {
+                    ids.push(MonitorId::EdidHash(hash));
+                }
+                if let Some(serial) = edid_serial(self.edid()) {
+                    ids.push(MonitorId::EdidSerial(serial));
+                }
+                ids
+            }
+            Display::Backlight(b) => vec![MonitorId::Backlight(b.name.clone())],
+            Display::Software(s) => vec![MonitorId::Software(s.label.clone())],
+            Display::Hid(h) => {
+                let mut ids = vec![MonitorId::Usb(h.vendor_id, h.product_id)];
+                if !h.serial.is_empty() {
+                    ids.push(MonitorId::Serial(h.serial.clone()));
+                }
+                ids
+            }
+        }
+    }
+
+    /// EDID of the display, empty if unknown.
+    fn edid(&self) -> &[u8] {
+        match self {
+            Display::Ddc { info, connector } => {
+                let edid = info.edid_bytes();
+                match connector {
+                    Some(c) if edid.is_empty() => &c.edid,
+                    _ => edid,
+                }
+            }
+            _ => &[],
+        }
+    }
+
+    /// Whether the identifier applies to this display.
+    pub fn matches(&self, id: &MonitorId) -> bool {
+        match id {
+            // each alternative has to apply on its own
+            MonitorId::Any(ids) => ids.iter().any(|id| self.matches(id)),
+            _ => self.is_named_by(id) && self.matches_values(id),
+        }
+    }
+
+    /// Whether the identifier names the kind of this display. Backlights, software brightness and
+    /// USB monitors have to be configured explicitly, even when combined with other identifiers.
+    fn is_named_by(&self, id: &MonitorId) -> bool {
+        match (self, id) {
+            (Display::Ddc { .. }, _) => true,
+            (Display::Backlight(_), MonitorId::Backlight(_))
+            | (Display::Software(_), MonitorId::Software(_))
+            | (Display::Hid(_), MonitorId::Usb(_, _)) => true,
+            (_, MonitorId::All(ids)) => ids.iter().any(|id| self.is_named_by(id)),
+            (_, MonitorId::Any(ids)) => {
+                !ids.is_empty() && ids.iter().all(|id| self.is_named_by(id))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the values of the identifier match this display, regardless of its kind.
+    fn matches_values(&self, id: &MonitorId) -> bool {
+        match (self, id) {
+            // default applies to any DDC monitor
+            (Display::Ddc { .. }, MonitorId::Default) => true,
+
+            // compare physical path of the display
+            (Display::Ddc { info: d, .. }, MonitorId::I2cBus(busno)) => {
+                d.path() == ddc::DisplayPath::I2C { bus: *busno as i32 }
+            }
+            (Display::Ddc { connector, .. }, MonitorId::Connector(name)) => {
+                connector.is_some_and(|c| c.name == *name)
+            }
+            (Display::Backlight(b), MonitorId::Backlight(name)) => b.name == *name,
+            (Display::Software(s), MonitorId::Software(label)) => s.label == *label,
+
+            // compare identifiers of the display
+            (Display::Ddc { info: d, .. }, MonitorId::Model(manufacturer, model)) => {
+                d.manufacturer() == manufacturer && d.model() == model
+            }
+            (Display::Ddc { info: d, .. }, MonitorId::ModelSerial(manufacturer, model, serial)) => {
+                d.manufacturer() == manufacturer
+                    && d.model() == model
+                    && d.serial_number() == serial
+            }
+            (Display::Ddc { info: d, .. }, MonitorId::Serial(serial)) => {
+                d.serial_number() == serial
+            }
+            (Display::Hid(h), MonitorId::Usb(vendor_id, product_id)) => {
+                h.vendor_id == *vendor_id && h.product_id == *product_id
+            }
+            (Display::Hid(h), MonitorId::Serial(serial)) => h.serial == *serial,
+            (Display::Ddc { .. }, MonitorId::EdidHash(hash)) => {
+                edid_hash(self.edid()).is_some_and(|h| h.eq_ignore_ascii_case(hash))
+            }
+            (Display::Ddc { .. }, MonitorId::EdidSerial(serial)) => {
+                edid_serial(self.edid()) == Some(*serial)
+            }
+
+            // patterns on identifiers of the display
+            (Display::Ddc { info: d, .. }, MonitorId::ModelGlob(manufacturer, model)) => {
+                glob_match(manufacturer, d.manufacturer()) && glob_match(model, d.model())
+            }
+            (Display::Ddc { info: d, .. }, MonitorId::SerialGlob(serial)) => {
+                glob_match(serial, d.serial_number())
+            }
+            (Display::Hid(h), MonitorId::SerialGlob(serial)) => glob_match(serial, &h.serial),
+
+            // combinations of identifiers, which can't be empty
+            (_, MonitorId::All(ids)) => {
+                !ids.is_empty() && ids.iter().all(|id| self.matches_values(id))
+            }
+            (_, MonitorId::Any(ids)) => ids.iter().any(|id| self.matches_values(id)),
+
+            _ => false,
+        }
+    }
+
+    /// Connect to the display.
+    pub fn open(&self) -> Result<Box<dyn Backend>, anyhow::Error> {
+        Ok(match self {
+            Display::Ddc { info, .. } => {
+                Box::new(Ddc(ddc::Display::from_display_info(info).anyhow()?))
+            }
+            Display::Backlight(b) => Box::new(b.open()?),
+            Display::Software(s) => Box::new(s.open()?),
+            Display::Hid(h) => Box::new(h.open()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    #[test]
+    fn explicit_kinds() {
+        let backlight = BacklightDevice {
+            name: "intel_backlight".to_string(),
+            path: PathBuf::from("/sys/class/backlight/intel_backlight"),
+        };
+        let hid = HidMonitor {
+            name: "Apple Inc. Studio Display".to_string(),
+            vendor_id: 0x05ac,
+            product_id: 0x1114,
+            serial: "ABC123".to_string(),
+            node: PathBuf::from("/dev/hidraw3"),
+            controls: vec![],
+        };
+        let (backlight, hid) = (Display::Backlight(&backlight), Display::Hid(&hid));
+        let id = |s: &str| ron::from_str::<MonitorId>(s).unwrap();
+
+        assert!(backlight.matches(&id(r#"Backlight("intel_backlight")"#)));
+        assert!(!backlight.matches(&id("Default")));
+        assert!(!backlight.matches(&id("All([])")));
+        assert!(!backlight.matches(&id("Any([])")));
+
+        assert!(hid.matches(&id("Usb(0x05ac, 0x1114)")));
+        assert!(hid.matches(&id(r#"All([Usb(0x05ac, 0x1114), Serial("ABC123")])"#)));
+        assert!(hid.matches(&id(r#"Any([Usb(0x05ac, 0x1114), Default])"#)));
+        assert!(!hid.matches(&id(r#"Serial("ABC123")"#)));
+        assert!(!hid.matches(&id(r#"All([SerialGlob("ABC*")])"#)));
+        assert!(!hid.matches(&id(r#"Any([Serial("ABC123"), Usb(0x05ac, 0x1)])"#)));
+    }
+}
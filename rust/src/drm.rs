@@ -0,0 +1,171 @@
+/// DRM connectors (e.g. "DP-2") from `/sys/class/drm`, and identifiers derived from EDID.
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::Context;
+
+pub const DRM_DIR: &str = "/sys/class/drm";
+
+/// A connector of a graphics card, e.g. `/sys/class/drm/card1-DP-2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrmConnector {
+    /// Name of the connector without the card, e.g. "DP-2"
+    pub name: String,
+    /// I2C buses that reach the connected display: the DDC bus, and the DisplayPort AUX channel
+    pub i2c_buses: Vec<u32>,
+    /// EDID of the connected display, empty if nothing is connected
+    pub edid: Vec<u8>,
+}
+
+impl DrmConnector {
+    /// List the connectors in the given directory. A missing directory means there are no
+    /// connectors.
+    pub fn detect<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>, anyhow::Error> {
+        let dir = dir.as_ref();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to list DRM connectors in {dir:?}"));
+            }
+        };
+
+        let mut connectors = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+
+            // Connectors are named "card<n>-<connector>", anything else is a card or render node
+            let Some((card, name)) = file_name.split_once('-') else {
+                continue;
+            };
+            if !card.starts_with("card") {
+                continue;
+            }
+
+            let path = entry.path();
+            let mut i2c_buses = Vec::new();
+            if let Ok(ddc) = fs::read_link(path.join("ddc"))
+                && let Some(bus) = ddc.file_name().and_then(|f| i2c_bus(&f.to_string_lossy()))
+            {
+                i2c_buses.push(bus);
+            }
+            for sub in fs::read_dir(&path)? {
+                if let Some(bus) = i2c_bus(&sub?.file_name().to_string_lossy()) {
+                    i2c_buses.push(bus);
+                }
+            }
+
+            connectors.push(DrmConnector {
+                name: name.to_string(),
+                i2c_buses,
+                edid: fs::read(path.join("edid")).unwrap_or_default(),
+            });
+        }
+        connectors.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(connectors)
+    }
+}
+
+/// Bus number of an "i2c-<n>" device name.
+fn i2c_bus(name: &str) -> Option<u32> {
+    name.strip_prefix("i2c-")?.parse().ok()
+}
+
+/// Stable hash of the EDID's base block, as 16 hex digits (64-bit FNV-1a).
+pub fn edid_hash(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 {
+        return None;
+    }
+    let hash = edid[..128].iter().fold(0xcbf29ce484222325u64, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    Some(format!("{hash:016x}"))
+}
+
+/// Binary serial number from the EDID's base block, if set. This is separate from the serial
+/// number string, and often set even if that is empty.
+pub fn edid_serial(edid: &[u8]) -> Option<u32> {
+    let serial = u32::from_le_bytes(edid.get(12..16)?.try_into().ok()?);
+    (serial != 0).then_some(serial)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    use std::os::unix::fs::symlink;
+
+    fn edid(serial: u32) -> Vec<u8> {
+        let mut edid = vec![0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+        edid.resize(128, 0);
+        edid[12..16].copy_from_slice(&serial.to_le_bytes());
+        edid
+    }
+
+    #[test]
+    fn detect() {
+        let dir = TempDir::new("drm");
+        for d in [
+            "card1",
+            "renderD128",
+            "card1-DP-2/i2c-7",
+            "card1-HDMI-A-1",
+            "card1-DP-3",
+        ] {
+            fs::create_dir_all(dir.join(d)).unwrap();
+        }
+        fs::write(dir.join("card1-DP-2/edid"), edid(1234)).unwrap();
+        fs::write(dir.join("card1-HDMI-A-1/edid"), edid(0)).unwrap();
+        symlink("../i2c-5", dir.join("card1-HDMI-A-1/ddc")).unwrap();
+
+        let connectors = DrmConnector::detect(&dir).unwrap();
+        assert_eq!(
+            vec![
+                DrmConnector {
+                    name: "DP-2".to_string(),
+                    i2c_buses: vec![7],
+                    edid: edid(1234),
+                },
+                DrmConnector {
+                    name: "DP-3".to_string(),
+                    i2c_buses: vec![],
+                    edid: vec![],
+                },
+                DrmConnector {
+                    name: "HDMI-A-1".to_string(),
+                    i2c_buses: vec![5],
+                    edid: edid(0),
+                },
+            ],
+            connectors
+        );
+
+        assert!(
+            DrmConnector::detect(dir.join("missing"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn edid_identifiers() {
+        assert_eq!(Some(1234), edid_serial(&edid(1234)));
+        assert_eq!(None, edid_serial(&edid(0)));
+        assert_eq!(None, edid_serial(&[]));
+
+        let hash = edid_hash(&edid(1234)).unwrap();
+        assert_eq!(16, hash.len());
+        assert_eq!(Some(hash.clone()), edid_hash(&edid(1234)));
+        assert_ne!(Some(hash), edid_hash(&edid(1235)));
+        assert_eq!(None, edid_hash(&[0; 10]));
+
+        // Only the base block counts, extension blocks may change with the display's settings
+        let mut extended = edid(1234);
+        extended.extend([1; 128]);
+        assert_eq!(edid_hash(&edid(1234)), edid_hash(&extended));
+    }
+}
@@ -0,0 +1,62 @@
+/// Simple glob patterns, to match monitor identifiers that vary slightly (e.g. by revision).
+///
+/// Whether `s` matches `pattern`, where `*` matches any number of characters and `?` matches a
+/// single character. Everything else must match exactly.
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+
+    // Position in the pattern and string to backtrack to after the last `*`
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut i) = (0, 0);
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((sp, si)) => {
+                    star = Some((sp, si + 1));
+                    p = sp + 1;
+                    i = si + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact() {
+        assert!(glob_match("G27Q", "G27Q"));
+        assert!(!glob_match("G27Q", "G27QC"));
+        assert!(!glob_match("G27Q", "G27"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "G27Q"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_match("G27Q*", "G27Q"));
+        assert!(glob_match("G27Q*", "G27QC"));
+        assert!(!glob_match("G27Q*", "M27Q"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*27*", "G27QC"));
+        assert!(glob_match("G?7Q", "G27Q"));
+        assert!(!glob_match("G?7Q", "G7Q"));
+        assert!(glob_match("*Q?", "G27QQC"));
+        assert!(!glob_match("*Q?", "G27QCC"));
+        assert!(glob_match("U*27*", "U2720Q U27"));
+    }
+}
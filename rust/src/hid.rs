@@ -0,0 +1,560 @@
+/// Monitors controlled over USB with the HID Monitor Control class (e.g. Apple Studio Display, LG
+/// UltraFine), through `/dev/hidraw*`. Controls on the VESA Virtual Controls usage page use the same
+/// codes as DDC/CI VCP features.
+use crate::backend::Backend;
+use crate::monitor::{BRIGHTNESS, CONTRAST};
+
+use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+pub const HIDRAW_DIR: &str = "/sys/class/hidraw";
+pub const DEV_DIR: &str = "/dev";
+
+/// Usage page of monitor controls, whose usages are VCP codes
+const VESA_VIRTUAL_CONTROLS: u16 = 0x82;
+
+/// Largest report count accepted in a report descriptor, far more than any monitor needs
+const MAX_REPORT_COUNT: usize = 4096;
+
+/// A control of the monitor, as a field of a feature report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Control {
+    /// VCP code of the control
+    pub code: u8,
+    report_id: u8,
+    /// Position of the field within the report, after the report ID
+    bit_offset: usize,
+    bit_size: usize,
+    logical_min: i64,
+    logical_max: i64,
+    /// Size of the whole report in bits, after the report ID
+    report_bits: usize,
+}
+
+/// A USB monitor found under `/sys/class/hidraw`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HidMonitor {
+    /// Product name reported by the device
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Serial number reported by the device, may be empty
+    pub serial: String,
+    /// Device node, e.g. `/dev/hidraw3`
+    pub node: PathBuf,
+    pub controls: Vec<Control>,
+}
+
+impl HidMonitor {
+    /// List the HID devices in the given directory that have monitor controls, with device nodes
+    /// in `dev_dir`. A missing directory means there are no HID devices.
+    pub fn detect<P: AsRef<Path>, D: AsRef<Path>>(
+        dir: P,
+        dev_dir: D,
+    ) -> Result<Vec<Self>, anyhow::Error> {
+        let dir = dir.as_ref();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to list HID devices in {dir:?}"));
+            }
+        };
+
+        let mut monitors = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let device = entry.path().join("device");
+
+            // Skip devices we can't read, they are unlikely to be monitors
+            let Ok(descriptor) = fs::read(device.join("report_descriptor")) else {
+                continue;
+            };
+            let controls = match parse_report_descriptor(&descriptor) {
+                Ok(controls) if controls.is_empty() => continue,
+                Ok(controls) => controls,
+                Err(err) => {
+                    eprintln!("Skipping HID device {device:?}, invalid report descriptor: {err:#}");
+                    continue;
+                }
+            };
+
+            // Don't let one broken device hide the others
+            let uevent = match fs::read_to_string(device.join("uevent")) {
+                Ok(uevent) => uevent,
+                Err(err) => {
+                    eprintln!("Skipping HID device {device:?}, failed to read its uevent: {err}");
+                    continue;
+                }
+            };
+            let field = |key: &str| {
+                uevent
+                    .lines()
+                    .find_map(|l| l.strip_prefix(key)?.strip_prefix('='))
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            // HID_ID is "<bus>:<vendor>:<product>" in hex
+            let id: Vec<_> = field("HID_ID")
+                .split(':')
+                .map(|s| u32::from_str_radix(s, 16).unwrap_or(0) as u16)
+                .collect();
+            monitors.push(HidMonitor {
+                name: field("HID_NAME"),
+                vendor_id: id.get(1).copied().unwrap_or(0),
+                product_id: id.get(2).copied().unwrap_or(0),
+                serial: field("HID_UNIQ"),
+                node: dev_dir.as_ref().join(entry.file_name()),
+                controls,
+            });
+        }
+        monitors.sort_by(|a, b| a.node.cmp(&b.node));
+        Ok(monitors)
+    }
+
+    pub fn open(&self) -> Result<Hid<Hidraw>, anyhow::Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.node)
+            .with_context(|| format!("Failed to open {0:?}", self.node))?;
+        Ok(Hid::new(Hidraw(file), self.controls.clone()))
+    }
+}
+
+/// Access to the feature reports of a HID device.
+pub trait FeatureReports: Debug + Send {
+    /// Read the report whose ID is in the first byte of `buf` into `buf`.
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Write the report whose ID is in the first byte of `buf`.
+    fn set_feature(&mut self, buf: &[u8]) -> io::Result<()>;
+}
+
+/// A `/dev/hidraw*` device node.
+#[derive(Debug)]
+pub struct Hidraw(File);
+
+impl Hidraw {
+    /// `_IOC(_IOC_READ | _IOC_WRITE, 'H', nr, len)` from `linux/hidraw.h`
+    fn ioctl_request(nr: u64, len: usize) -> u64 {
+        (3 << 30) | ((len as u64) << 16) | ((b'H' as u64) << 8) | nr
+    }
+}
+
+impl FeatureReports for Hidraw {
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        // HIDIOCGFEATURE
+        let request = Self::ioctl_request(0x07, buf.len());
+        let res = unsafe { libc::ioctl(self.0.as_raw_fd(), request as _, buf.as_mut_ptr()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn set_feature(&mut self, buf: &[u8]) -> io::Result<()> {
+        // HIDIOCSFEATURE
+        let request = Self::ioctl_request(0x06, buf.len());
+        let res = unsafe { libc::ioctl(self.0.as_raw_fd(), request as _, buf.as_ptr()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// USB monitor controlled through feature reports. Brightness and contrast are scaled from the
+/// control's logical range to a percentage, other controls use raw values.
+#[derive(Debug)]
+pub struct Hid<R> {
+    device: R,
+    controls: Vec<Control>,
+}
+
+impl<R: FeatureReports> Hid<R> {
+    pub fn new(device: R, controls: Vec<Control>) -> Self {
+        Hid { device, controls }
+    }
+
+    fn control(&self, code: u8) -> Result<Control, anyhow::Error> {
+        self.controls
+            .iter()
+            .find(|c| c.code == code)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("USB monitor doesn't support VCP feature {code:#04x}"))
+    }
+
+    /// Read the whole report containing the control.
+    fn read_report(&mut self, control: &Control) -> Result<Vec<u8>, anyhow::Error> {
+        let mut report = vec![0; 1 + control.report_bits.div_ceil(8)];
+        report[0] = control.report_id;
+        self.device
+            .get_feature(&mut report)
+            .with_context(|| format!("Failed to read feature report {0}", control.report_id))?;
+        Ok(report)
+    }
+}
+
+impl<R: FeatureReports> Backend for Hid<R> {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        let control = self.control(code)?;
+        let report = self.read_report(&control)?;
+        let raw = get_bits(&report[1..], control.bit_offset, control.bit_size) as i64;
+
+        Ok(match code {
+            BRIGHTNESS | CONTRAST => {
+                let range = (control.logical_max - control.logical_min).max(1) as f64;
+                let raw = raw.clamp(control.logical_min, control.logical_max);
+                ((raw - control.logical_min) as f64 * 100.0 / range).round() as u16
+            }
+            _ => raw as u16,
+        })
+    }
+
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        let control = self.control(code)?;
+        let raw = match code {
+            BRIGHTNESS | CONTRAST => {
+                let range = (control.logical_max - control.logical_min) as f64;
+                control.logical_min + (value.min(100) as f64 * range / 100.0).round() as i64
+            }
+            _ => (value as i64).clamp(control.logical_min, control.logical_max),
+        };
+
+        // Other fields of the report must keep their values
+        let mut report = self.read_report(&control)?;
+        set_bits(
+            &mut report[1..],
+            control.bit_offset,
+            control.bit_size,
+            raw as u64,
+        );
+        self.device
+            .set_feature(&report)
+            .with_context(|| format!("Failed to write feature report {0}", control.report_id))
+    }
+}
+
+fn get_bits(data: &[u8], offset: usize, size: usize) -> u64 {
+    (0..size.min(64))
+        .filter(|i| data[(offset + i) / 8] & (1 << ((offset + i) % 8)) != 0)
+        .fold(0, |value, i| value | 1 << i)
+}
+
+fn set_bits(data: &mut [u8], offset: usize, size: usize, value: u64) {
+    for i in 0..size.min(64) {
+        let (byte, bit) = ((offset + i) / 8, (offset + i) % 8);
+        if value & (1 << i) != 0 {
+            data[byte] |= 1 << bit;
+        } else {
+            data[byte] &= !(1 << bit);
+        }
+    }
+}
+
+/// Global items of a report descriptor, which apply to all following main items.
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_min: i64,
+    logical_max: i64,
+    report_size: usize,
+    report_id: u8,
+    report_count: usize,
+}
+
+/// Find the monitor controls in a HID report descriptor: the fields of feature reports with usages
+/// on the VESA Virtual Controls page.
+fn parse_report_descriptor(descriptor: &[u8]) -> Result<Vec<Control>, anyhow::Error> {
+    let mut controls = Vec::new();
+    let mut globals = Globals::default();
+    let mut stack = Vec::new();
+    // Local items: usages as (page, id), and a usage range
+    let mut usages: Vec<(u16, u16)> = Vec::new();
+    let mut usage_min: Option<(u16, u16)> = None;
+    // Size of the feature reports so far, by report ID
+    let mut offsets = [0usize; 256];
+
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+
+        // Long items aren't used by anything we care about
+        if prefix == 0xfe {
+            i += 3 + descriptor.get(i + 1).copied().unwrap_or(0) as usize;
+            continue;
+        }
+
+        let size = [0, 1, 2, 4][(prefix & 0x3) as usize];
+        let Some(data) = descriptor.get(i + 1..i + 1 + size) else {
+            break;
+        };
+        i += 1 + size;
+        let unsigned = data
+            .iter()
+            .rev()
+            .fold(0u32, |value, &b| value << 8 | b as u32);
+        let signed = match size {
+            1 => unsigned as u8 as i8 as i64,
+            2 => unsigned as u16 as i16 as i64,
+            _ => unsigned as i32 as i64,
+        };
+
+        match (prefix >> 2) & 0x3 {
+            // Main items
+            0 => {
+                let tag = prefix >> 4;
+                // Feature, unless the field is constant (padding)
+                if tag == 0xb {
+                    let report_id = globals.report_id as usize;
+                    if unsigned & 0x1 == 0 {
+                        for n in 0..globals.report_count {
+                            let usage = match usage_min {
+                                Some((page, min)) => Some((
+                                    page,
+                                    min.checked_add(n as u16).with_context(|| {
+                                        format!("Usage range from {min:#06x} is too long")
+                                    })?,
+                                )),
+                                None => usages.get(n).or(usages.last()).copied(),
+                            };
+                            if let Some((VESA_VIRTUAL_CONTROLS, code @ 0..=0xff)) = usage {
+                                controls.push(Control {
+                                    code: code as u8,
+                                    report_id: globals.report_id,
+                                    bit_offset: offsets[report_id] + n * globals.report_size,
+                                    bit_size: globals.report_size,
+                                    logical_min: globals.logical_min,
+                                    logical_max: globals.logical_max,
+                                    report_bits: 0,
+                                });
+                            }
+                        }
+                    }
+                    offsets[report_id] += globals.report_count * globals.report_size;
+                }
+                usages.clear();
+                usage_min = None;
+            }
+            // Global items
+            1 => match prefix >> 4 {
+                0x0 => globals.usage_page = unsigned as u16,
+                0x1 => globals.logical_min = signed,
+                0x2 => {
+                    // Treat the maximum as unsigned if it only makes sense that way
+                    globals.logical_max = if signed < globals.logical_min {
+                        unsigned as i64
+                    } else {
+                        signed
+                    }
+                }
+                0x7 => globals.report_size = unsigned as usize,
+                0x8 => globals.report_id = unsigned as u8,
+                0x9 => {
+                    globals.report_count = unsigned as usize;
+                    if globals.report_count > MAX_REPORT_COUNT {
+                        anyhow::bail!("Report count {unsigned} is above {MAX_REPORT_COUNT}");
+                    }
+                }
+                0xa => stack.push(globals),
+                0xb => globals = stack.pop().unwrap_or_default(),
+                _ => {}
+            },
+            // Local items, where 4-byte usages include the usage page
+            2 => {
+                let usage = if size == 4 {
+                    ((unsigned >> 16) as u16, unsigned as u16)
+                } else {
+                    (globals.usage_page, unsigned as u16)
+                };
+                match prefix >> 4 {
+                    0x0 => usages.push(usage),
+                    0x1 => usage_min = Some(usage),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for control in &mut controls {
+        control.report_bits = offsets[control.report_id as usize];
+    }
+    Ok(controls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    use std::collections::BTreeMap;
+
+    /// Report descriptor with brightness in report 1 (32 bits, 400 to 60000), and contrast plus a
+    /// vendor-specific field in report 2.
+    #[rustfmt::skip]
+    const DESCRIPTOR: &[u8] = &[
+        0x05, 0x80,                   // Usage Page (Monitor)
+        0x09, 0x01,                   // Usage (Monitor Control)
+        0xa1, 0x01,                   // Collection (Application)
+        0x85, 0x01,                   //   Report ID (1)
+        0x05, 0x82,                   //   Usage Page (VESA Virtual Controls)
+        0x09, 0x10,                   //   Usage (Brightness)
+        0x16, 0x90, 0x01,             //   Logical Minimum (400)
+        0x27, 0x60, 0xea, 0x00, 0x00, //   Logical Maximum (60000)
+        0x75, 0x20,                   //   Report Size (32)
+        0x95, 0x01,                   //   Report Count (1)
+        0xb1, 0x02,                   //   Feature (Data, Variable, Absolute)
+        0x09, 0x10,                   //   Usage (Brightness)
+        0x81, 0x02,                   //   Input (Data, Variable, Absolute)
+        0x85, 0x02,                   //   Report ID (2)
+        0x75, 0x04,                   //   Report Size (4)
+        0xb1, 0x03,                   //   Feature (Constant), padding
+        0x06, 0x00, 0xff,             //   Usage Page (Vendor)
+        0x09, 0x01,                   //   Usage (1)
+        0x09, 0x12,                   //   Usage (0x12)
+        0x15, 0x00,                   //   Logical Minimum (0)
+        0x26, 0xff, 0x00,             //   Logical Maximum (255)
+        0x75, 0x08,                   //   Report Size (8)
+        0xb1, 0x02,                   //   Feature (Data, Variable, Absolute)
+        0x0b, 0x12, 0x00, 0x82, 0x00, //   Usage (VESA Virtual Controls, Contrast)
+        0xb1, 0x02,                   //   Feature (Data, Variable, Absolute)
+        0xc0,                         // End Collection
+    ];
+
+    fn brightness() -> Control {
+        Control {
+            code: BRIGHTNESS,
+            report_id: 1,
+            bit_offset: 0,
+            bit_size: 32,
+            logical_min: 400,
+            logical_max: 60000,
+            report_bits: 32,
+        }
+    }
+
+    fn contrast() -> Control {
+        Control {
+            code: CONTRAST,
+            report_id: 2,
+            bit_offset: 12,
+            bit_size: 8,
+            logical_min: 0,
+            logical_max: 255,
+            report_bits: 20,
+        }
+    }
+
+    #[test]
+    fn parse_descriptor() {
+        assert_eq!(
+            vec![brightness(), contrast()],
+            parse_report_descriptor(DESCRIPTOR).unwrap()
+        );
+        assert!(
+            parse_report_descriptor(&[0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0xc0])
+                .unwrap()
+                .is_empty()
+        );
+
+        // Report Count (4097)
+        assert!(parse_report_descriptor(&[0x96, 0x01, 0x10, 0xb1, 0x02]).is_err());
+        // Usage Minimum (0xffff) with Report Count (2), going past the last usage
+        assert!(
+            parse_report_descriptor(&[
+                0x05, 0x82, 0x1a, 0xff, 0xff, 0x75, 0x08, 0x95, 0x02, 0xb1, 0x02
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn detect() {
+        let dir = TempDir::new("hidraw");
+        let devices = [
+            (
+                "hidraw0",
+                &[0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0xc0][..],
+                "Keyboard",
+                "",
+            ),
+            ("hidraw3", DESCRIPTOR, "Apple Inc. Studio Display", "ABC123"),
+            ("hidraw5", DESCRIPTOR, "Unreadable", ""),
+        ];
+        for (node, descriptor, name, serial) in devices {
+            let device = dir.join(node).join("device");
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("report_descriptor"), descriptor).unwrap();
+            let uevent =
+                format!("HID_ID=0003:000005AC:00001114\nHID_NAME={name}\nHID_UNIQ={serial}\n");
+            fs::write(device.join("uevent"), uevent).unwrap();
+        }
+        // A device whose uevent can't be read is skipped
+        fs::remove_file(dir.join("hidraw5/device/uevent")).unwrap();
+
+        assert_eq!(
+            vec![HidMonitor {
+                name: "Apple Inc. Studio Display".to_string(),
+                vendor_id: 0x05ac,
+                product_id: 0x1114,
+                serial: "ABC123".to_string(),
+                node: PathBuf::from("/dev/hidraw3"),
+                controls: vec![brightness(), contrast()],
+            }],
+            HidMonitor::detect(&dir, "/dev").unwrap()
+        );
+        assert!(
+            HidMonitor::detect(dir.join("missing"), "/dev")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    /// Fake hidraw device, with the contents of each feature report.
+    #[derive(Debug, Default)]
+    struct FakeHidraw {
+        reports: BTreeMap<u8, Vec<u8>>,
+    }
+
+    impl FeatureReports for FakeHidraw {
+        fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<()> {
+            let report = self.reports.get(&buf[0]).ok_or(ErrorKind::InvalidInput)?;
+            buf.copy_from_slice(report);
+            Ok(())
+        }
+
+        fn set_feature(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.reports.insert(buf[0], buf.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut device = FakeHidraw::default();
+        device.reports.insert(1, vec![1, 0x90, 0x01, 0, 0]);
+        device.reports.insert(2, vec![2, 0x0f, 0xf0, 0xaf]);
+        let mut hid = Hid::new(device, vec![brightness(), contrast()]);
+
+        assert_eq!(0, hid.get(BRIGHTNESS).unwrap());
+        hid.set(BRIGHTNESS, 50).unwrap();
+        assert_eq!(vec![1, 0xf8, 0x75, 0, 0], hid.device.reports[&1]);
+        assert_eq!(50, hid.get(BRIGHTNESS).unwrap());
+
+        // Fields around the control are unchanged
+        assert_eq!(100, hid.get(CONTRAST).unwrap());
+        hid.set(CONTRAST, 20).unwrap();
+        assert_eq!(vec![2, 0x0f, 0x30, 0xa3], hid.device.reports[&2]);
+        assert_eq!(20, hid.get(CONTRAST).unwrap());
+
+        assert!(hid.get(0x14).is_err());
+    }
+}
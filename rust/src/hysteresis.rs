@@ -0,0 +1,139 @@
+/// Decides when a target value should change, so small fluctuations in lux don't cause the monitor to
+/// oscillate over a small range.
+use crate::config::Hysteresis;
+
+use std::time::{Duration, Instant};
+
+/// Hysteresis state of a single feature.
+#[derive(Debug, Default)]
+pub struct HysteresisState {
+    /// Lux and time of the last target change
+    last_change: Option<(f64, Instant)>,
+}
+
+impl HysteresisState {
+    /// Calculate the new target, given the current value and target, and the value from the curve at
+    /// the current lux.
+    pub fn new_target(
+        &mut self,
+        policy: &Hysteresis,
+        cur: u16,
+        target: u16,
+        new: u16,
+        lux: f64,
+        now: Instant,
+    ) -> u16 {
+        let accept = match *policy {
+            Hysteresis::Round {
+                step,
+                low_step,
+                low_threshold,
+            } => {
+                let round_to = if new > low_threshold { step } else { low_step };
+                return round_towards(cur, new, round_to);
+            }
+            Hysteresis::LuxDeadband(pct) => match self.last_change {
+                None => true,
+                Some((last_lux, _)) => (lux - last_lux).abs() > last_lux * pct / 100.0,
+            },
+            Hysteresis::MinInterval(ms) => match self.last_change {
+                None => true,
+                Some((_, last_time)) => now.duration_since(last_time) >= Duration::from_millis(ms),
+            },
+        };
+
+        if !accept {
+            target
+        } else {
+            if new != target {
+                self.last_change = Some((lux, now));
+            }
+            new
+        }
+    }
+}
+
+/// Round to the multiple of `round_to` that is closest to current value.
+///
+/// This prevents the target from moving too erratically, reducing how often we make updates
+/// oscillating over a small range
+fn round_towards(cur: u16, new: u16, round_to: u16) -> u16 {
+    let round_to = round_to.max(1);
+
+    if new == cur {
+        new
+    } else if new < cur {
+        new.div_ceil(round_to) * round_to
+    } else {
+        new / round_to * round_to
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round() {
+        let policy = Hysteresis::default();
+        let now = Instant::now();
+        let target =
+            |cur, new| HysteresisState::default().new_target(&policy, cur, cur, new, 0.0, now);
+
+        // Finer rounding at the low end (see also monitor::tests::test_new_target_brightness)
+        assert_eq!(10, target(10, 11));
+        assert_eq!(12, target(10, 12));
+        assert_eq!(8, target(10, 8));
+        assert_eq!(8, target(10, 7));
+    }
+
+    #[test]
+    fn lux_deadband() {
+        let policy = Hysteresis::LuxDeadband(20.0);
+        let now = Instant::now();
+        let mut state = HysteresisState::default();
+
+        // First target is always accepted
+        assert_eq!(50, state.new_target(&policy, 0, 0, 50, 100.0, now));
+
+        // Small changes in lux don't change the target
+        for lux in 80..=120 {
+            assert_eq!(
+                50,
+                state.new_target(&policy, 50, 50, 40 + lux as u16 / 10, lux as f64, now)
+            );
+        }
+
+        // Larger changes do, relative to the lux of the last change
+        assert_eq!(53, state.new_target(&policy, 50, 50, 53, 121.0, now));
+        assert_eq!(53, state.new_target(&policy, 53, 53, 55, 145.0, now));
+        assert_eq!(56, state.new_target(&policy, 53, 53, 56, 146.0, now));
+        assert_eq!(56, state.new_target(&policy, 56, 56, 54, 117.0, now));
+        assert_eq!(54, state.new_target(&policy, 56, 56, 54, 116.0, now));
+    }
+
+    #[test]
+    fn min_interval() {
+        let policy = Hysteresis::MinInterval(10_000);
+        let start = Instant::now();
+        let at = |s| start + Duration::from_secs(s);
+        let mut state = HysteresisState::default();
+
+        // First target is always accepted
+        assert_eq!(50, state.new_target(&policy, 0, 0, 50, 100.0, at(0)));
+
+        // Target doesn't change again until the interval passed
+        for s in 1..10 {
+            assert_eq!(
+                50,
+                state.new_target(&policy, 50, 50, 46 + s as u16, 100.0, at(s))
+            );
+        }
+        assert_eq!(47, state.new_target(&policy, 50, 50, 47, 100.0, at(10)));
+        assert_eq!(47, state.new_target(&policy, 47, 47, 60, 100.0, at(19)));
+
+        // The same target doesn't count as a change
+        assert_eq!(47, state.new_target(&policy, 47, 47, 47, 100.0, at(25)));
+        assert_eq!(60, state.new_target(&policy, 47, 47, 60, 100.0, at(26)));
+    }
+}
@@ -0,0 +1,158 @@
+/// Learns a personalized brightness curve from manual brightness adjustments, and persists what was learned.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Combines the configured brightness curve with (lux, brightness) samples recorded when the user
+/// corrected the brightness manually.
+#[derive(Debug)]
+pub struct CurveLearner {
+    base: Vec<(f64, u32)>,
+    samples: Vec<(f64, u32)>,
+}
+
+impl CurveLearner {
+    /// Maximum number of samples to remember. When exceeded, the oldest samples are forgotten.
+    const MAX_SAMPLES: usize = 32;
+
+    /// Construct a learner from the configured curve and any previously learned samples.
+    pub fn new(base: Vec<(f64, u32)>, samples: Vec<(f64, u32)>) -> Self {
+        let mut learner = CurveLearner {
+            base,
+            samples: Vec::new(),
+        };
+        for (lux, brightness) in samples {
+            learner.add_sample(lux, brightness);
+        }
+        learner
+    }
+
+    /// Samples recorded so far, oldest first.
+    pub fn samples(&self) -> &[(f64, u32)] {
+        &self.samples
+    }
+
+    /// Two points conflict if they have the same lux, or if the curve would need to decrease between them.
+    fn conflicts(a: (f64, u32), b: (f64, u32)) -> bool {
+        a.0 == b.0 || (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1)
+    }
+
+    /// Record a manual adjustment. Older samples that conflict with the new one are discarded, so the
+    /// most recent correction always wins.
+    pub fn add_sample(&mut self, lux: f64, brightness: u32) {
+        let sample = (lux, brightness);
+        self.samples.retain(|&s| !Self::conflicts(s, sample));
+        self.samples.push(sample);
+
+        if self.samples.len() > Self::MAX_SAMPLES {
+            let excess = self.samples.len() - Self::MAX_SAMPLES;
+            self.samples.drain(..excess);
+        }
+    }
+
+    /// Fit the curve to the recorded samples.
+    ///
+    /// The result passes through every sample, plus the points of the configured curve that are
+    /// consistent with them. Points of the configured curve that would make the curve decrease around a
+    /// sample are dropped, so the curve stays monotonic if the configured one was.
+    pub fn curve(&self) -> Vec<(f64, u32)> {
+        let mut curve: Vec<(f64, u32)> = self
+            .base
+            .iter()
+            .copied()
+            .filter(|&p| !self.samples.iter().any(|&s| Self::conflicts(p, s)))
+            .chain(self.samples.iter().copied())
+            .collect();
+        curve.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        curve
+    }
+}
+
+/// Learned samples for each display, as stored in the state file.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct LearnedCurves {
+    /// Samples keyed by the display's "manufacturer model serial connector" string.
+    pub displays: BTreeMap<String, Vec<(f64, u32)>>,
+}
+
+impl LearnedCurves {
+    pub fn read_from_file<P: AsRef<Path>>(file: P) -> Result<Self, anyhow::Error> {
+        Ok(ron::de::from_reader(BufReader::new(File::open(file)?))?)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, file: P) -> Result<(), anyhow::Error> {
+        let format_opts = ron::ser::PrettyConfig::new().indentor("  ");
+        ron::Options::default().to_io_writer_pretty(File::create(file)?, self, format_opts)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_samples() {
+        let learner = CurveLearner::new(vec![(0.0, 10), (250.0, 100)], vec![]);
+        assert_eq!(vec![(0.0, 10), (250.0, 100)], learner.curve());
+    }
+
+    #[test]
+    fn sample_inserted_into_curve() {
+        let learner = CurveLearner::new(vec![(0.0, 10), (250.0, 100)], vec![(100.0, 30)]);
+        assert_eq!(vec![(0.0, 10), (100.0, 30), (250.0, 100)], learner.curve());
+    }
+
+    #[test]
+    fn sample_replaces_conflicting_base_points() {
+        let learner = CurveLearner::new(
+            vec![(0.0, 10), (50.0, 40), (100.0, 60), (250.0, 100)],
+            vec![(60.0, 20), (200.0, 100)],
+        );
+        assert_eq!(
+            vec![
+                (0.0, 10),
+                (60.0, 20),
+                (100.0, 60),
+                (200.0, 100),
+                (250.0, 100)
+            ],
+            learner.curve()
+        );
+    }
+
+    #[test]
+    fn newer_sample_wins() {
+        let mut learner = CurveLearner::new(vec![(0.0, 10), (250.0, 100)], vec![]);
+        learner.add_sample(100.0, 50);
+        learner.add_sample(150.0, 60);
+        learner.add_sample(120.0, 70);
+        assert_eq!(&[(100.0, 50), (120.0, 70)], learner.samples());
+
+        learner.add_sample(120.0, 40);
+        learner.add_sample(200.0, 80);
+        assert_eq!(&[(120.0, 40), (200.0, 80)], learner.samples());
+        assert_eq!(
+            vec![(0.0, 10), (120.0, 40), (200.0, 80), (250.0, 100)],
+            learner.curve()
+        );
+    }
+
+    #[test]
+    fn sample_limit() {
+        let mut learner = CurveLearner::new(vec![], vec![]);
+        for i in 0..100 {
+            learner.add_sample(i as f64, i);
+        }
+        assert_eq!(CurveLearner::MAX_SAMPLES, learner.samples().len());
+        assert_eq!(
+            (
+                100.0 - CurveLearner::MAX_SAMPLES as f64,
+                100 - CurveLearner::MAX_SAMPLES as u32
+            ),
+            learner.samples()[0]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+/// Converts between a monitor's brightness percentage and the luminance it produces, so monitors
+/// with different peak luminance can be matched by targeting the same number of nits (cd/m²).
+use crate::piecewise_linear::PiecewiseLinear;
+
+/// Measured luminance of a monitor at different brightness values, interpolated linearly between
+/// the measured points.
+#[derive(Debug)]
+pub struct LuminanceProfile {
+    to_nits: PiecewiseLinear<u32, u32>,
+    to_brightness: PiecewiseLinear<u32, u32>,
+}
+
+impl LuminanceProfile {
+    /// Construct a profile from (brightness, nits) pairs.
+    ///
+    /// Returns None if there are no points, or if the luminance doesn't strictly increase with the
+    /// brightness, since then a luminance can't be converted back to a single brightness.
+    pub fn new(mut points: Vec<(u32, u32)>) -> Option<Self> {
+        points.sort();
+        if points
+            .windows(2)
+            .any(|w| w[0].0 >= w[1].0 || w[0].1 >= w[1].1)
+        {
+            return None;
+        }
+
+        let inverse = points.iter().map(|&(b, nits)| (nits, b)).collect();
+        Some(LuminanceProfile {
+            to_nits: PiecewiseLinear::from_steps(points)?,
+            to_brightness: PiecewiseLinear::from_steps(inverse)?,
+        })
+    }
+
+    /// Luminance in nits at the given brightness.
+    pub fn nits(&self, brightness: u32) -> u32 {
+        self.to_nits.eval(brightness)
+    }
+
+    /// Brightness that produces the given luminance in nits, limited to the measured range.
+    pub fn brightness(&self, nits: u32) -> u32 {
+        self.to_brightness.eval(nits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_profile() {
+        assert!(LuminanceProfile::new(vec![]).is_none());
+        assert!(LuminanceProfile::new(vec![(0, 50), (50, 50)]).is_none());
+        assert!(LuminanceProfile::new(vec![(0, 50), (50, 40)]).is_none());
+        assert!(LuminanceProfile::new(vec![(50, 40), (50, 60)]).is_none());
+        assert!(LuminanceProfile::new(vec![(50, 80), (0, 40)]).is_some());
+    }
+
+    #[test]
+    fn conversion() {
+        // A dim monitor and a bright one, targeting the same luminance
+        let dim = LuminanceProfile::new(vec![(0, 40), (50, 150), (100, 250)]).unwrap();
+        let bright = LuminanceProfile::new(vec![(0, 80), (100, 480)]).unwrap();
+
+        assert_eq!(50, dim.brightness(150));
+        // Not exact, since brightness and nits are integers
+        assert_eq!(18, bright.brightness(150));
+        assert_eq!(152, bright.nits(18));
+
+        // Out of range luminance is limited to what the monitor can do
+        assert_eq!(100, dim.brightness(400));
+        assert_eq!(0, bright.brightness(50));
+    }
+}
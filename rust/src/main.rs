@@ -1,22 +1,52 @@
 // in-crate modules
+mod backend;
+mod backlight;
 mod config;
+mod display;
+mod drm;
+mod glob;
+mod hid;
+mod hysteresis;
+mod learning;
+mod luminance;
 mod monitor;
+mod night;
+mod offset;
 mod piecewise_linear;
+mod plot;
+mod software;
+mod status;
+#[cfg(test)]
+mod test_util;
+mod transition;
 mod tsl2591;
+mod worker;
+mod writer;
 
 // in-crate imports
+use backend::{Backend, Closed, Extended};
 use config::*;
+use display::{Display, Displays};
+use learning::*;
+use luminance::LuminanceProfile;
 use monitor::*;
+use night::is_night;
+use offset::{Offsets, find_display};
 use piecewise_linear::*;
+use plot::{Axis, Series};
+use status::*;
 use tsl2591::TSL2591;
+use worker::{MonitorWorker, run_all};
+use writer::VcpWriter;
 
 // my libraries
 use ddc::{self, ConvertToAnyhow};
 use xdg_dirs::{dirs, xdg_location_of, xdg_user_dir};
 
 // STD
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{fs, thread, time};
 
 // 3rd party libraries
@@ -25,6 +55,9 @@ use clap::{Parser, Subcommand, command};
 use ftdi_embedded_hal as hal;
 
 const CONFIG_PATH: &str = "adaptive-brightness/config.ron";
+const LEARNED_PATH: &str = "adaptive-brightness/learned.ron";
+const STATUS_PATH: &str = "adaptive-brightness/status.ron";
+const OFFSETS_PATH: &str = "adaptive-brightness/offsets.ron";
 
 const DEFAULT_CONFIG: &str = r#"
 (
@@ -55,6 +88,56 @@ enum Command {
     #[command(about = "Generate a default config file")]
     GenConfig,
 
+    #[command(about = "Show the status of each display reported by the running daemon.")]
+    Status,
+
+    #[command(
+        about = "Show or change the brightness offset of a monitor, applied by the running daemon on top of its curve."
+    )]
+    Offset {
+        #[arg(help = "Name of the monitor as shown by `status`, or a unique part of it")]
+        monitor: Option<String>,
+
+        #[arg(
+            allow_negative_numbers = true,
+            help = "New offset, or the amount to change it by with `--relative`"
+        )]
+        offset: Option<i32>,
+
+        #[arg(
+            short,
+            long,
+            help = "Change the offset by the given amount instead of setting it"
+        )]
+        relative: bool,
+    },
+
+    #[command(
+        about = "Plot the brightness the daemon sets each monitor to for each lux value, including offsets, limits and learned adjustments, marking the current lux value."
+    )]
+    Plot {
+        #[arg(
+            long,
+            help = "Lux value to mark. Defaults to the last value read by the running daemon."
+        )]
+        lux: Option<f64>,
+
+        #[arg(
+            long,
+            help = "Highest lux value to plot. Defaults to a bit past the last point of the curves."
+        )]
+        max_lux: Option<f64>,
+
+        #[arg(long, help = "Plot lux on a logarithmic scale")]
+        log: bool,
+
+        #[arg(
+            long,
+            help = "Write all curves to an SVG file instead of drawing them in the terminal"
+        )]
+        svg: Option<PathBuf>,
+    },
+
     // TODO remove
     #[command(about = "for testing")]
     Test,
@@ -116,40 +199,185 @@ fn get_config(args: &Args) -> anyhow::Result<Config> {
     }
 }
 
-/// Get list of displays from the DDC library, and wrapp the error because they aren't sync so anyhow doesn't like them.
-fn get_displays() -> anyhow::Result<ddc::DisplayInfoList> {
-    ddc::get_display_info_list(false).anyhow()
+/// Load brightness samples learned from manual adjustments in previous runs, if there are any.
+fn get_learned_curves() -> LearnedCurves {
+    let Ok(path) = xdg_location_of(&dirs::STATE, LEARNED_PATH) else {
+        return LearnedCurves::default();
+    };
+
+    println!("Reading learned curves from {path}", path = path.display());
+    LearnedCurves::read_from_file(&path).unwrap_or_else(|err| {
+        eprintln!("Failed to read learned curves, starting from scratch: {err}");
+        LearnedCurves::default()
+    })
+}
+
+/// Save learned brightness samples to the state directory.
+fn save_learned_curves(learned: &LearnedCurves) -> anyhow::Result<()> {
+    let path = xdg_user_dir(&dirs::STATE, LEARNED_PATH)
+        .with_context(|| "Could not determine location for learned curves")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory {0}", parent.display()))?;
+    }
+
+    learned
+        .write_to_file(&path)
+        .with_context(|| format!("Failed to write learned curves to {0}", path.display()))
+}
+
+/// Load brightness offsets set with the `offset` command, if there are any.
+fn get_offsets() -> Offsets {
+    let Ok(path) = xdg_location_of(&dirs::STATE, OFFSETS_PATH) else {
+        return Offsets::default();
+    };
+
+    Offsets::read_from_file(&path).unwrap_or_else(|err| {
+        eprintln!("Failed to read brightness offsets, ignoring them: {err}");
+        Offsets::default()
+    })
+}
+
+/// When the offsets file was last changed, or None if it doesn't exist.
+fn offsets_modified() -> Option<time::SystemTime> {
+    let path = xdg_location_of(&dirs::STATE, OFFSETS_PATH).ok()?;
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Save brightness offsets to the state directory, where the daemon picks them up.
+fn save_offsets(offsets: &Offsets) -> anyhow::Result<()> {
+    let path = xdg_user_dir(&dirs::STATE, OFFSETS_PATH)
+        .with_context(|| "Could not determine location for brightness offsets")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory {0}", parent.display()))?;
+    }
+
+    offsets
+        .write_to_file(&path)
+        .with_context(|| format!("Failed to write brightness offsets to {0}", path.display()))
+}
+
+/// Write the status of each monitor to the state directory, for the `status` and `check` commands.
+fn save_status(monitors: &[MonitorWorker], lux: f64) -> anyhow::Result<()> {
+    let status = DaemonStatus {
+        pid: std::process::id(),
+        lux: Some(lux),
+        monitors: run_all(monitors, |m| (m.name().to_string(), m.status()))
+            .into_iter()
+            .flatten()
+            .collect(),
+    };
+
+    let path = xdg_user_dir(&dirs::STATE, STATUS_PATH)
+        .with_context(|| "Could not determine location for status file")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory {0}", parent.display()))?;
+    }
+
+    status
+        .write_to_file(&path)
+        .with_context(|| format!("Failed to write status to {0}", path.display()))
+}
+
+/// Read the status written by the daemon, and whether the daemon that wrote it is still running.
+fn get_status() -> anyhow::Result<(DaemonStatus, bool)> {
+    let path = xdg_location_of(&dirs::STATE, STATUS_PATH)
+        .with_context(|| "Could not find status file, is the daemon running?")?;
+    let status = DaemonStatus::read_from_file(&path)
+        .with_context(|| format!("Failed to read status from {0}", path.display()))?;
+    let running = status.is_running();
+
+    Ok((status, running))
+}
+
+/// Print a single line describing the status of a monitor.
+fn print_monitor_status(status: &MonitorStatus) {
+    let stats = &status.stats;
+    match &status.health {
+        Health::Ok => print!("ok"),
+        Health::Degraded(err) => print!("DEGRADED: {err}"),
+    }
+    println!(
+        " (writes={0}, retries={1}, failures={2})",
+        stats.writes, stats.retries, stats.failures
+    );
+}
+
+/// Show the status reported by the daemon.
+fn print_status() -> anyhow::Result<()> {
+    let (status, running) = get_status()?;
+    if running {
+        println!("Daemon running with pid {0}", status.pid);
+    } else {
+        println!("Daemon not running, last status from pid {0}:", status.pid);
+    }
+    if let Some(lux) = status.lux {
+        println!("Last lux reading: {lux:.1}");
+    }
+
+    for (name, monitor) in &status.monitors {
+        print!("    {name}: ");
+        print_monitor_status(monitor);
+    }
+
+    Ok(())
+}
+
+/// Show the runtime brightness offset of each monitor, or change the offset of one monitor.
+fn change_offset(monitor: Option<&str>, offset: Option<i32>, relative: bool) -> anyhow::Result<()> {
+    let mut offsets = get_offsets();
+    let Some(monitor) = monitor else {
+        if offsets.displays.is_empty() {
+            println!("No brightness offsets set");
+        }
+        for (name, offset) in &offsets.displays {
+            println!("    {name}: {offset:+}");
+        }
+        return Ok(());
+    };
+
+    // Monitors known to the daemon, and any that have an offset but aren't connected right now
+    let status = get_status().map(|(status, _)| status).unwrap_or_default();
+    let names: BTreeSet<String> = status
+        .monitors
+        .keys()
+        .chain(offsets.displays.keys())
+        .cloned()
+        .collect();
+    let name = find_display(names.iter().map(String::as_str), monitor)?.to_string();
+
+    let current = offsets.get(&name);
+    let new = match offset {
+        None => {
+            println!("{name}: {current:+}");
+            return Ok(());
+        }
+        Some(offset) if relative => current + offset,
+        Some(offset) => offset,
+    };
+    offsets.set(&name, new);
+    save_offsets(&offsets)?;
+    println!("{name}: {current:+} -> {new:+}");
+
+    Ok(())
+}
+
+/// Detect displays of all kinds.
+fn get_displays() -> anyhow::Result<Displays> {
+    Displays::detect()
 }
 
 /// Match up display configuration to the detected displays.
 fn match_displays_to_config<'d, 'c>(
-    displays: &'d ddc::DisplayInfoList,
+    displays: &'d Displays,
     config: &'c Config,
-) -> anyhow::Result<Vec<(&'d ddc::DisplayInfo, Option<&'c MonitorConfig>)>> {
+) -> anyhow::Result<Vec<(Display<'d>, Option<&'c MonitorConfig>)>> {
     let ret = displays
-        .into_iter()
+        .iter()
         .map(|d| {
-            let matching = config.monitors.iter().find(|&m| match &m.identifier {
-                // default always applies
-                MonitorId::Default => true,
-
-                // compare physical path of the display
-                MonitorId::I2cBus(busno) => {
-                    d.path() == ddc::DisplayPath::I2C { bus: *busno as i32 }
-                }
-
-                // compare identifiers of the display
-                MonitorId::Model(manufacturer, model) => {
-                    d.manufacturer() == manufacturer && d.model() == model
-                }
-                MonitorId::ModelSerial(manufacturer, model, serial) => {
-                    d.manufacturer() == manufacturer
-                        && d.model() == model
-                        && d.serial_number() == serial
-                }
-                MonitorId::Serial(serial) => d.serial_number() == serial,
-            });
-
+            let matching = config.monitors.iter().find(|&m| d.matches(&m.identifier));
             (d, matching)
         })
         .collect();
@@ -157,6 +385,46 @@ fn match_displays_to_config<'d, 'c>(
     Ok(ret)
 }
 
+/// Required monitor configurations that didn't match any of the detected displays.
+fn missing_required_monitors<'c>(
+    config_mapping: &[(Display, Option<&MonitorConfig>)],
+    config: &'c Config,
+) -> Vec<&'c MonitorId> {
+    config
+        .monitors
+        .iter()
+        .filter(|mc| mc.required)
+        .filter(|&mc| {
+            !config_mapping
+                .iter()
+                .any(|(_, m)| m.is_some_and(|m| std::ptr::eq(m, mc)))
+        })
+        .map(|mc| &mc.identifier)
+        .collect()
+}
+
+/// Detect displays, waiting up to the configured timeout for all required monitors to show up.
+fn wait_for_required_displays(config: &Config) -> anyhow::Result<Displays> {
+    let deadline = time::Instant::now() + time::Duration::from_secs(config.required_timeout_secs);
+    loop {
+        let displays = get_displays()?;
+        let missing =
+            missing_required_monitors(&match_displays_to_config(&displays, config)?, config);
+        if missing.is_empty() {
+            return Ok(displays);
+        }
+        if time::Instant::now() >= deadline {
+            anyhow::bail!("Required monitors not detected: {missing:?}");
+        }
+
+        println!("Waiting for required monitors: {missing:?}");
+        thread::sleep(time::Duration::from_secs(2));
+        // Redetecting invalidates the display information
+        drop(displays);
+        ddc::redetect_displays().anyhow()?;
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
@@ -178,6 +446,24 @@ fn main() -> anyhow::Result<()> {
         // Generate config file: if the file does not already exist, write
         Some(Command::GenConfig) => gen_config_file(&args),
 
+        // Show status reported by the daemon
+        Some(Command::Status) => print_status(),
+
+        // Show or change runtime brightness offsets
+        Some(Command::Offset {
+            monitor,
+            offset,
+            relative,
+        }) => change_offset(monitor.as_deref(), offset, relative),
+
+        // Show brightness curves
+        Some(Command::Plot {
+            lux,
+            max_lux,
+            log,
+            ref svg,
+        }) => plot_curves(&args, lux, max_lux, log, svg.as_deref()),
+
         Some(Command::Test) => test(&args),
     }
 }
@@ -200,21 +486,39 @@ fn check_config(args: &Args) -> anyhow::Result<()> {
     let displays = get_displays()?;
     let config_mapping = match_displays_to_config(&displays, &config)?;
 
-    for (display, conf) in config_mapping {
-        println!(
-            "Display {0}: {1} {2} {3}",
-            display.display_no(),
-            display.manufacturer(),
-            display.model(),
-            display.serial_number()
-        );
+    // Include the status of each display if the daemon is running
+    let daemon_status = get_status()
+        .ok()
+        .filter(|&(_, running)| running)
+        .map(|(status, _)| status);
+
+    for &(display, conf) in &config_mapping {
+        println!("Display {0} ({1})", display.name(), display.device());
+        println!("  State key: {0}", display.state_key());
+        for id in display.identifiers() {
+            println!("  Identifier: {id:?}");
+        }
         match conf {
             None => println!("  No matching configuration!"),
-            Some(conf) => println!("  Matched: {0:?}", conf),
+            Some(conf) => {
+                println!("  Matched: {0:?}", conf);
+                match &conf.preset {
+                    Some(preset) => println!("  Curve {preset:?}: {0:?}", conf.curve_points()),
+                    None => println!("  Curve: {0:?}", conf.curve_points()),
+                }
+            }
+        }
+        if let Some(monitor) = daemon_status
+            .as_ref()
+            .and_then(|s| s.monitors.get(&display.state_key()))
+        {
+            print!("  Daemon status: ");
+            print_monitor_status(monitor);
         }
     }
-
-    // TODO: compare configuration against list of displays, list brightness curve for each detected display
+    for id in missing_required_monitors(&config_mapping, &config) {
+        println!("Required monitor not detected: {id:?}");
+    }
 
     Ok(())
 }
@@ -242,18 +546,39 @@ fn gen_config_file(args: &Args) -> anyhow::Result<()> {
     // Detect displays to write default config
     let displays = get_displays()?;
 
+    // Backlights and software displays are often the same screens as DDC monitors, so they are
+    // left for the user to add explicitly
     let monitors = displays
-        .into_iter()
+        .iter()
+        .filter(|d| matches!(d, Display::Ddc { .. } | Display::Hid(_)))
         .map(|d| MonitorConfig {
-            identifier: MonitorId::ModelSerial(
-                d.manufacturer().to_string(),
-                d.model().to_string(),
-                d.serial_number().to_string(),
-            ),
-            curve: vec![(0, 10), (250, 100)],
+            identifier: d.identifier(),
+            curve: Curve::Preset("default".to_string()),
+            contrast: None,
+            features: vec![],
+            night: vec![],
+            learn: false,
+            interpolation: Interpolation::Linear,
+            interpolations: BTreeMap::new(),
+            transition: TransitionConfig::default(),
+            hysteresis: Hysteresis::default(),
+            write: WritePolicy::default(),
+            required: false,
+            extended_dimming: None,
+            min: None,
+            max: None,
+            offset: 0,
+            luminance: None,
+            preset: None,
         })
         .collect::<Vec<_>>();
-    let conf = Config { monitors: monitors };
+    let conf = Config {
+        monitors: monitors,
+        curves: BTreeMap::from([("default".to_string(), vec![(0.0, 10), (250.0, 100)])]),
+        night: None,
+        hotplug_interval_secs: default_hotplug_interval(),
+        required_timeout_secs: 0,
+    };
 
     // Create the new file and write the default contents
     let file = File::create_new(&path)
@@ -265,6 +590,327 @@ fn gen_config_file(args: &Args) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The brightness curve of a monitor, as the daemon follows it until it learns anything.
+fn brightness_curve(mc: &MonitorConfig) -> anyhow::Result<PiecewiseLinear> {
+    Ok(PiecewiseLinear::from_steps(mc.brightness_curve())
+        .ok_or_else(|| {
+            anyhow::anyhow!("Invalid brightness curve for monitor {0:?}", mc.identifier)
+        })?
+        .with_interpolation(mc.interpolation_of(BRIGHTNESS)))
+}
+
+/// Plot the brightness each monitor is set to for each lux value, in the terminal or to an SVG file.
+///
+/// Connected displays include their runtime offsets and learned adjustments, like in the daemon.
+/// Configured monitors that aren't connected are plotted with just their configuration.
+fn plot_curves(
+    args: &Args,
+    lux: Option<f64>,
+    max_lux: Option<f64>,
+    log: bool,
+    svg: Option<&Path>,
+) -> anyhow::Result<()> {
+    let config = get_config(args)?;
+    let displays = get_displays();
+    let config_mapping = match &displays {
+        Ok(displays) => match_displays_to_config(displays, &config)?,
+        Err(err) => {
+            eprintln!("Failed to detect displays, plotting the configuration only: {err:#}");
+            vec![]
+        }
+    };
+    let learned = get_learned_curves();
+    let offsets = get_offsets();
+
+    // Evaluate each monitor the same way the daemon does, without a connection to the display
+    let plotted = |name: String, device: String, mc: &MonitorConfig, samples| {
+        let writer = VcpWriter::new(Box::new(Closed), mc.write);
+        let mut m = configure_monitor(name, device, writer, mc, samples)?;
+        m.set_user_offset(offsets.get(m.name()));
+        // Show the brightness in the units of the config, below 0 with extended dimming
+        let shift = mc.extended_dimming.as_ref().map_or(0.0, |e| e.range as f64);
+        anyhow::Ok(move |lux| m.brightness_for_lux(lux) as f64 - shift)
+    };
+    let mut series = Vec::new();
+    let mut configs = Vec::new();
+    for &(d, mc) in &config_mapping {
+        let Some(mc) = mc else { continue };
+        series.push(Series {
+            name: format!("{0} ({1:?})", d.state_key(), mc.identifier),
+            value: Box::new(plotted(
+                d.state_key(),
+                d.device(),
+                mc,
+                learned_samples(&learned, d),
+            )?),
+        });
+        configs.push(mc);
+    }
+    for mc in &config.monitors {
+        if config_mapping
+            .iter()
+            .any(|(_, m)| m.is_some_and(|m| std::ptr::eq(m, mc)))
+        {
+            continue;
+        }
+        series.push(Series {
+            name: format!("{0:?} (not connected)", mc.identifier),
+            value: Box::new(plotted(String::new(), String::new(), mc, vec![])?),
+        });
+        configs.push(mc);
+    }
+
+    // Leave some room after the last point, so it's visible where the curve levels off
+    let last_point = config
+        .monitors
+        .iter()
+        .flat_map(|mc| mc.curve_points())
+        .map(|&(lux, _)| lux)
+        .fold(0.0, f64::max);
+    let axis = Axis {
+        max: max_lux.unwrap_or((last_point * 1.2).max(10.0)).ceil(),
+        log,
+    };
+    let lux = lux.or_else(|| get_status().ok().and_then(|(status, _)| status.lux));
+
+    if let Some(path) = svg {
+        fs::write(path, plot::svg(&series, &axis, lux))
+            .with_context(|| format!("Failed to write plot to {0}", path.display()))?;
+        println!("Wrote plot to {0}", path.display());
+        return Ok(());
+    }
+
+    for (s, mc) in series.iter().zip(configs) {
+        print!("{0}", s.name);
+        if let Some(preset) = &mc.preset {
+            print!(" (curve {preset:?})");
+        }
+        if let Some(lux) = lux {
+            print!(": brightness {0} at {lux:.1} lux", (s.value)(lux));
+        }
+        println!();
+        println!("{0}", plot::terminal(s, &axis, lux, 60, 15));
+    }
+    Ok(())
+}
+
+/// Connect to a display, combined with its software display if dimming is extended.
+fn open_display(
+    d: Display,
+    mc: &MonitorConfig,
+    displays: &Displays,
+) -> anyhow::Result<Box<dyn Backend>> {
+    let backend = d.open()?;
+    let Some(extended) = &mc.extended_dimming else {
+        return Ok(backend);
+    };
+
+    let software = displays
+        .software(&extended.software)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Software display {0:?} for extended dimming not found",
+                extended.software
+            )
+        })?
+        .open()?;
+    Ok(Box::new(Extended::new(backend, software, extended.range)))
+}
+
+/// Open a display and build its state from the matching configuration.
+fn build_monitor(
+    d: Display,
+    mc: &MonitorConfig,
+    displays: &Displays,
+    learned: &LearnedCurves,
+) -> anyhow::Result<MonitorState> {
+    let writer = VcpWriter::new(open_display(d, mc, displays)?, mc.write);
+    configure_monitor(
+        d.state_key(),
+        d.device(),
+        writer,
+        mc,
+        learned_samples(learned, d),
+    )
+}
+
+/// Brightness samples learned for a display.
+fn learned_samples(learned: &LearnedCurves, d: Display) -> Vec<(f64, u32)> {
+    learned
+        .displays
+        .get(&d.state_key())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Build a monitor's state from its configuration, controlling the display through `d`, starting
+/// from the given learned samples.
+fn configure_monitor(
+    name: String,
+    device: String,
+    d: VcpWriter,
+    mc: &MonitorConfig,
+    samples: Vec<(f64, u32)>,
+) -> anyhow::Result<MonitorState> {
+    let curve = brightness_curve(mc)?;
+
+    let mut state = MonitorState::for_display(name, device, d, curve);
+    if let Some(extended) = &mc.extended_dimming {
+        state = state.with_extended_dimming(extended.range);
+    }
+    let (min, max) = mc.brightness_limits();
+    state = state.with_limits(min, max, mc.offset);
+    if let Some(profile) = mc.luminance_profile() {
+        let profile = LuminanceProfile::new(profile).ok_or_else(|| {
+            anyhow::anyhow!("Invalid luminance profile for monitor {0:?}", mc.identifier)
+        })?;
+        state = state.with_luminance(profile);
+    }
+    let features = mc
+        .contrast
+        .iter()
+        .map(|c| (CONTRAST, c))
+        .chain(mc.features.iter().map(|(code, c)| (*code, c)));
+    for (code, curve) in features {
+        let curve = PiecewiseLinear::from_steps(curve.clone())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid curve for VCP feature {code:#04x} of monitor {0:?}",
+                    mc.identifier
+                )
+            })?
+            .with_interpolation(mc.interpolation_of(code));
+        state = state.with_feature(code, curve)?;
+    }
+    for &(code, day, night) in &mc.night {
+        state = state.with_night_feature(code, day, night)?;
+    }
+
+    state = state
+        .with_transition(mc.transition)
+        .with_hysteresis(mc.hysteresis);
+
+    if mc.learn {
+        state.learn_from(CurveLearner::new(mc.brightness_curve(), samples))
+    } else {
+        Ok(state)
+    }
+}
+
+/// Set a monitor's initial state for the given lux and night state. If that fails, the monitor is
+/// marked degraded.
+fn init_monitor(m: &mut MonitorState, lux: f64, night: Option<bool>) {
+    let res = m.set_for_lux(lux).and_then(|_| match night {
+        Some(night) => m.set_night(night),
+        None => Ok(()),
+    });
+    if let Err(err) = res {
+        m.mark_degraded(err);
+    }
+}
+
+/// Detect displays again, to start controlling newly connected displays and stop controlling
+/// disconnected ones. Monitors that are still connected keep their state.
+///
+/// Returns true if any monitors were added or removed.
+fn rescan_displays(
+    monitors: &mut Vec<MonitorWorker>,
+    config: &Config,
+    learned: &LearnedCurves,
+    lux: f64,
+    night: Option<bool>,
+) -> anyhow::Result<bool> {
+    // Redetecting invalidates every open DDC/CI display, so close them all first and reopen the
+    // ones that are still connected afterwards. Monitors that are still closed from an earlier
+    // rescan are reopened too.
+    let is_closed = run_all(monitors, |m| {
+        if m.uses_ddc() {
+            m.close();
+        }
+        m.is_closed()
+    });
+    let closed: BTreeSet<(String, String)> = monitors
+        .iter()
+        .zip(is_closed)
+        .filter(|&(_, is_closed)| is_closed == Some(true))
+        .map(|(m, _)| (m.name().to_string(), m.device().to_string()))
+        .collect();
+
+    let count = monitors.len();
+    let changed = redetect_displays(monitors, &closed, config, learned, lux, night)?;
+    Ok(changed || monitors.len() != count)
+}
+
+/// Reopen the `closed` monitors that are still connected, drop the disconnected ones, and add
+/// newly connected ones. If detection fails, all monitors are kept, and the closed ones stay
+/// closed until the next rescan.
+///
+/// Returns true if any monitors were added.
+fn redetect_displays(
+    monitors: &mut Vec<MonitorWorker>,
+    closed: &BTreeSet<(String, String)>,
+    config: &Config,
+    learned: &LearnedCurves,
+    lux: f64,
+    night: Option<bool>,
+) -> anyhow::Result<bool> {
+    ddc::redetect_displays().anyhow()?;
+    let displays = get_displays()?;
+    let config_mapping = match_displays_to_config(&displays, config)?;
+
+    // Drop monitors that are no longer connected
+    let connected: Vec<(String, String)> = config_mapping
+        .iter()
+        .filter(|(_, mc)| mc.is_some())
+        .map(|(d, _)| (d.state_key(), d.device()))
+        .collect();
+    monitors.retain(|m| {
+        let key = (m.name().to_string(), m.device().to_string());
+        let keep = connected.contains(&key);
+        if !keep {
+            println!("{0} ({1}): disconnected", m.name(), m.device());
+        }
+        keep
+    });
+    let mut changed = false;
+
+    for (d, mc) in config_mapping {
+        let Some(mc) = mc else { continue };
+        let key = (d.state_key(), d.device());
+
+        // Reopen monitors that are still connected
+        if let Some(m) = monitors
+            .iter()
+            .find(|m| m.name() == key.0 && m.device() == key.1)
+        {
+            if closed.contains(&key) {
+                match open_display(d, mc, &displays) {
+                    Ok(backend) => {
+                        m.start(|m| m.reopen(backend));
+                    }
+                    Err(err) => eprintln!("{0} ({1}): failed to reopen: {err:#}", key.0, key.1),
+                }
+            }
+            continue;
+        }
+
+        // Add newly connected monitors
+        let (name, device) = key;
+        println!("{name} ({device}): connected");
+        match build_monitor(d, mc, &displays, learned) {
+            Ok(m) => {
+                let m = MonitorWorker::spawn(m);
+                m.start(move |m| init_monitor(m, lux, night));
+                monitors.push(m);
+                changed = true;
+            }
+            Err(err) => eprintln!("{name} ({device}): failed to set up: {err:#}"),
+        }
+    }
+
+    Ok(changed)
+}
+
 /// Default daemon behaviour: Read config file, then read brightness and update each monitor forever.
 fn main_loop(args: &Args) -> anyhow::Result<()> {
     // Read in configuration, or load default configuration
@@ -272,51 +918,67 @@ fn main_loop(args: &Args) -> anyhow::Result<()> {
     println!("Loaded configuration: {config:?}");
 
     // Detect displays and match them up with configuration settings
-    let displays = get_displays()?;
+    let displays = wait_for_required_displays(&config)?;
     let config_mapping = match_displays_to_config(&displays, &config)?;
 
     println!("Detected displays:");
     for (d, conf) in &config_mapping {
-        print!(
-            "    {0:<3}  {1:<13}  {2:<13}: ",
-            d.manufacturer(),
-            d.model(),
-            d.serial_number()
-        );
+        print!("    {0:<35}: ", d.name());
         match conf {
             None => println!("no matching config"),
-            Some(mc) => println!("curve={0:?}", mc.curve),
+            Some(mc) => {
+                print!("curve=");
+                if let Some(preset) = &mc.preset {
+                    print!("{preset} ");
+                }
+                print!("{0:?}", mc.curve_points());
+                if mc.interpolation != Interpolation::Linear {
+                    print!(", interpolation={0:?}", mc.interpolation);
+                }
+                if !mc.interpolations.is_empty() {
+                    print!(", interpolations={0:?}", mc.interpolations);
+                }
+                if let Some(extended) = &mc.extended_dimming {
+                    print!(", extended_dimming={extended:?}");
+                }
+                if let Some(luminance) = &mc.luminance {
+                    print!(", luminance={luminance:?}");
+                }
+                if mc.min.is_some() || mc.max.is_some() || mc.offset != 0 {
+                    print!(
+                        ", min={0:?}, max={1:?}, offset={2}",
+                        mc.min, mc.max, mc.offset
+                    );
+                }
+                if let Some(contrast) = &mc.contrast {
+                    print!(", contrast={contrast:?}");
+                }
+                for (code, curve) in &mc.features {
+                    print!(", {code:#04x}={curve:?}");
+                }
+                for (code, day, night) in &mc.night {
+                    print!(", {code:#04x}=(day: {day}, night: {night})");
+                }
+                println!();
+            }
         }
     }
 
     // Construct internal state for each device
-    let mut monitors: Vec<MonitorState> = config_mapping
+    let mut learned = get_learned_curves();
+    let mut monitors: Vec<MonitorWorker> = config_mapping
         .iter()
-        .filter_map(|&(ref d, mc)| {
-            // filter out monitors that don't match any config
-            if let Some(mc) = mc {
-                Some((*d, mc))
-            } else {
-                None
-            }
-        })
-        .map(|(d, mc)| {
-            // Open each display and build their state
-            let curve = PiecewiseLinear::from_steps(mc.curve.clone()).ok_or_else(|| {
-                anyhow::anyhow!("Invalid brightness curve for monitor {0:?}", mc.identifier)
-            })?;
-
-            let d = ddc::Display::from_display_info(d).anyhow()?;
-
-            Ok(MonitorState::for_display(d, curve))
-        })
+        .filter_map(|&(d, mc)| mc.map(|mc| (d, mc))) // filter out monitors that don't match any config
+        .map(|(d, mc)| build_monitor(d, mc, &displays, &learned).map(MonitorWorker::spawn))
         .collect::<anyhow::Result<Vec<_>>>()?;
 
     // Sanity check: if no monitors, there's nothing to do
     if monitors.len() < 1 {
         anyhow::bail!("no monitors detected matching any configuration values, exiting ...");
     }
-// TODO should make monitors "required" so we can fail early if _some_ monitors aren't present but some are
+    // Display information is invalidated when displays are redetected
+    drop(config_mapping);
+    drop(displays);
 
     // Connect to the brightness sensor
     let device = ftdi::find_by_vid_pid(0x0403, 0x6014)
@@ -330,39 +992,112 @@ fn main_loop(args: &Args) -> anyhow::Result<()> {
     // Description = USB <-> Serial Converter
     // SerialNumber = FTA3Q3CS
 
+    // Apply offsets from previous runs before setting the initial brightness
+    let mut offsets_modified_at = offsets_modified();
+    let mut offsets = get_offsets();
+    for m in &monitors {
+        let offset = offsets.get(m.name());
+        m.start(move |m| m.set_user_offset(offset));
+    }
+
     // Set initial brightness based on current state
-    let lux = sensor.read_lux()? as u32;
-    for m in &mut monitors {
-        m.set_brightness_for_lux(lux)?;
+    let lux = sensor.read_lux()?;
+    let night = config.night.as_ref().map(is_night);
+    run_all(&monitors, move |m| init_monitor(m, lux, night));
+    if let Err(err) = save_status(&monitors, lux) {
+        eprintln!("Failed to save status: {err:#}");
     }
 
     let mut iters_since_last_update = 0;
+    let hotplug_interval = time::Duration::from_secs(config.hotplug_interval_secs);
+    let mut last_rescan = time::Instant::now();
 
     // Main loop: periodically wake up to update all monitors
     loop {
-        let mut updated = false;
-        let lux = sensor.read_lux()? as u32;
+        // Shortest transition interval of the monitors that are still off-target, if any
+        let mut transition_interval: Option<time::Duration> = None;
+        let lux = sensor.read_lux()?;
+        let night = config.night.as_ref().map(is_night);
+
+        // Periodically check for displays being connected or disconnected
+        let mut health_changed = false;
+        if !hotplug_interval.is_zero() && last_rescan.elapsed() >= hotplug_interval {
+            match rescan_displays(&mut monitors, &config, &learned, lux, night) {
+                Ok(changed) => {
+                    last_rescan = time::Instant::now();
+                    health_changed |= changed;
+                }
+                // The closed DDC/CI monitors are skipped until they are reopened, so try again on
+                // the next iteration rather than after the full interval
+                Err(err) => eprintln!("Failed to detect displays: {err:#}"),
+            }
+        }
+
+        // Pick up offsets changed with the `offset` command. Also applies them to new monitors.
+        let modified = offsets_modified();
+        if modified != offsets_modified_at {
+            offsets_modified_at = modified;
+            offsets = get_offsets();
+        }
+        for m in &monitors {
+            let offset = offsets.get(m.name());
+            m.start(move |m| m.set_user_offset(offset));
+        }
+
+        // Update all monitors at the same time, so they change together
+        let updates = run_all(&monitors, move |m| {
+            let off_target = m.update_isolated(lux, night);
+            (
+                off_target.then(|| m.transition_interval()),
+                m.take_health_changed(),
+                m.take_learned_samples(),
+            )
+        });
+        let mut learned_changed = false;
+        for (m, update) in monitors.iter().zip(updates) {
+            // A monitor whose update panicked was marked degraded
+            let (interval, changed, samples) = update.unwrap_or((None, true, None));
+            if let Some(interval) = interval {
+                transition_interval =
+                    Some(transition_interval.map_or(interval, |i| i.min(interval)));
+            }
+            health_changed |= changed;
 
-        for m in &mut monitors {
-            updated = updated || m.update_brightness(lux)?;
+            // Persist anything learned from manual adjustments
+            if let Some(samples) = samples {
+                learned.displays.insert(m.name().to_string(), samples);
+                learned_changed = true;
+            }
+        }
+        if learned_changed && let Err(err) = save_learned_curves(&learned) {
+            eprintln!("Failed to save learned curves: {err:#}");
         }
 
-        if updated {
+        let mut save_status_now = health_changed;
+        if transition_interval.is_some() {
             iters_since_last_update = 0;
         } else {
             iters_since_last_update += 1;
             if iters_since_last_update >= 100 {
                 iters_since_last_update = 0;
-                println!("lux={lux}");
+                save_status_now = true;
+                println!("lux={lux:.1}");
+                let stats = run_all(&monitors, |m| *m.write_stats());
+                for (m, stats) in monitors.iter().zip(stats) {
+                    if let Some(stats) = stats
+                        && (stats.retries > 0 || stats.failures > 0)
+                    {
+                        println!("    {0}: {stats:?}", m.name());
+                    }
+                }
             }
         }
+        if save_status_now && let Err(err) = save_status(&monitors, lux) {
+            eprintln!("Failed to save status: {err:#}");
+        }
 
         // Don't sleep as long if we may be off-target
-        thread::sleep(time::Duration::from_millis(if updated {
-            100
-        } else {
-            5_000
-        }));
+        thread::sleep(transition_interval.unwrap_or(time::Duration::from_millis(5_000)));
     }
 }
 
@@ -438,5 +1173,17 @@ mod tests {
             },
             Args::try_parse_from(&["executable", "--config", "/some/file", "run"]).unwrap()
         );
+
+        assert_eq!(
+            Args {
+                config_path: None,
+                command: Some(Command::Offset {
+                    monitor: Some("G27Q".to_string()),
+                    offset: Some(-5),
+                    relative: true,
+                }),
+            },
+            Args::try_parse_from(["executable", "offset", "G27Q", "-5", "--relative"]).unwrap()
+        );
     }
 }
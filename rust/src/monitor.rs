@@ -1,108 +1,697 @@
 /// Wrapper for a single monitor that handles updating its brightness and remembers its state.
+use crate::backend::{Backend, Closed};
+use crate::config::{Hysteresis, TransitionConfig};
+use crate::hysteresis::HysteresisState;
+use crate::learning::CurveLearner;
+use crate::luminance::LuminanceProfile;
 use crate::piecewise_linear::PiecewiseLinear;
+use crate::status::{Health, MonitorStatus};
+use crate::transition::Transition;
+use crate::writer::{VcpWriter, WriteStats};
 
-use ddc::{self, ConvertToAnyhow};
+use std::time::{Duration, Instant};
 
+/// A VCP feature of the monitor (e.g. brightness) whose value follows a curve based on lux.
+///
+/// Brightness and contrast are percentages and are limited to 100. Other features are set to the raw
+/// value from the curve. The value from the curve is shifted by `offset` before being limited.
 #[derive(Debug)]
-pub struct MonitorState {
+struct VcpFeature {
     // Configuration
-    display: ddc::Display,
+    code: u8,
     curve: PiecewiseLinear,
+    /// Converts the curve from nits to brightness, if the curve is in nits
+    luminance: Option<LuminanceProfile>,
+    /// Added to the value from the curve
+    offset: i32,
+    /// Lowest value to write
+    min: u16,
+    /// Highest value to write
+    max: u16,
 
     // State
     target: u16,
-    brightness: u16,
+    value: u16,
+    transition: Option<Transition>,
+    hysteresis: HysteresisState,
+}
+
+impl VcpFeature {
+    fn new(code: u8, curve: PiecewiseLinear) -> Self {
+        let max = match code {
+            BRIGHTNESS | CONTRAST => 100,
+            _ => u16::MAX,
+        };
+        VcpFeature {
+            code,
+            curve,
+            luminance: None,
+            offset: 0,
+            min: 0,
+            max,
+            target: 0,
+            value: 0,
+            transition: None,
+            hysteresis: HysteresisState::default(),
+        }
+    }
+
+    /// Human-readable name of the feature for logging.
+    fn name(&self) -> String {
+        match self.code {
+            BRIGHTNESS => "brightness".to_string(),
+            CONTRAST => "contrast".to_string(),
+            0x16 => "red gain".to_string(),
+            0x18 => "green gain".to_string(),
+            0x1A => "blue gain".to_string(),
+            code => format!("feature {code:#04x}"),
+        }
+    }
+
+    /// Replace the points of the curve, keeping its interpolation. Returns None if the points don't
+    /// make a valid curve.
+    fn replace_curve(&mut self, points: Vec<(f64, u32)>) -> Option<()> {
+        let interpolation = self.curve.interpolation();
+        self.curve = PiecewiseLinear::from_steps(points)?.with_interpolation(interpolation);
+        Some(())
+    }
+
+    /// Value from the curve for the given lux value, after applying the offset and limits.
+    fn target_for_lux(&self, lux: f64) -> u16 {
+        let mut value = self.curve.eval(lux);
+        if let Some(luminance) = &self.luminance {
+            value = luminance.brightness(value);
+        }
+        let value = value as i64 + self.offset as i64;
+        value.clamp(self.min as i64, self.max as i64) as u16
+    }
+
+    /// Read the current value of the feature from the monitor.
+    fn get(&self, display: &mut VcpWriter) -> Result<u16, anyhow::Error> {
+        display.get(self.code)
+    }
+
+    /// Set the feature to the given value unconditionally.
+    fn set(&mut self, display: &mut VcpWriter, value: u16) -> Result<(), anyhow::Error> {
+        let value = value.clamp(self.min, self.max);
+
+        display.set(self.code, value)?;
+
+        self.value = value;
+        Ok(())
+    }
+
+    /// Set the feature based on the given lux value unconditionally. Used for initialization.
+    fn set_for_lux(&mut self, display: &mut VcpWriter, lux: f64) -> Result<(), anyhow::Error> {
+        let target = self.target_for_lux(lux);
+        println!(
+            "setting initial {0}: lux={lux:.1}, setting={target}",
+            self.name()
+        );
+        self.transition = None;
+        self.target = target;
+        self.set(display, target)
+    }
+
+    /// Move the feature one step towards the target for the given lux value.
+    ///
+    /// Returns true if new value does not match the target, false otherwise.
+    fn update(
+        &mut self,
+        display: &mut VcpWriter,
+        lux: f64,
+        config: &TransitionConfig,
+        hysteresis: &Hysteresis,
+    ) -> Result<bool, anyhow::Error> {
+        let cur = self.value;
+
+        self.target = self.hysteresis.new_target(
+            hysteresis,
+            cur,
+            self.target,
+            self.target_for_lux(lux),
+            lux,
+            Instant::now(),
+        );
+        let target = self.target;
+
+        if cur == target {
+            self.transition = None;
+            return Ok(false);
+        }
+
+        // Start a new transition from the current value whenever the target changes
+        let transition = match &self.transition {
+            Some(t) if t.target() == target => t,
+            _ => self
+                .transition
+                .insert(Transition::start(cur, target, config)),
+        };
+
+        let new_b = if cur.abs_diff(target) <= config.snap {
+            target
+        } else {
+            // Take the first step right away rather than waiting an interval
+            transition.value_at(transition.elapsed() + Duration::from_millis(config.interval_ms))
+        };
+
+        if new_b != cur {
+            println!(
+                "lux={lux:.1}, {0} target={target}, setting={new_b}",
+                self.name()
+            );
+            self.set(display, new_b)?;
+        }
+        if new_b == target {
+            self.transition = None;
+        }
+
+        Ok(new_b != target)
+    }
+}
+
+/// A VCP feature of the monitor (e.g. colour preset) that switches between a day and a night value.
+#[derive(Debug)]
+struct NightFeature {
+    code: u8,
+    day: u16,
+    night: u16,
+}
+
+/// VCP feature code for brightness
+pub const BRIGHTNESS: u8 = 0x10;
+/// VCP feature code for contrast
+pub const CONTRAST: u8 = 0x12;
+
+#[derive(Debug)]
+pub struct MonitorState {
+    // Configuration
+    name: String,
+    device: String,
+    display: VcpWriter,
+    learner: Option<CurveLearner>,
+    transition: TransitionConfig,
+    hysteresis: Hysteresis,
+    /// Brightness offset from the configuration
+    offset: i32,
+
+    // State
+    /// Features to control. The first is always brightness.
+    features: Vec<VcpFeature>,
+    night_features: Vec<NightFeature>,
+    /// Whether the night values are currently applied, or None if not set yet
+    is_night: Option<bool>,
+    /// Brightness offset set at runtime, on top of the configured one
+    user_offset: i32,
+    learned_new_sample: bool,
+    health: Health,
+    health_changed: bool,
+    /// When to next try to recover, if degraded
+    retry_at: Instant,
+    /// Whether the display was closed and not reopened yet. Updates are skipped meanwhile.
+    closed: bool,
 }
 
 impl MonitorState {
-    /// Brightness target is always rounded to a multiple of this constant.
-    const ROUND_TO_NEAREST: u16 = 5;
+    /// How long to wait before retrying a degraded monitor.
+    const DEGRADED_RETRY_INTERVAL: Duration = Duration::from_secs(60);
 
     /// Construct a `MonitorState` with the given brightness curve from a `DisplayInfo`.
-    pub fn for_display(display: ddc::Display, curve: PiecewiseLinear) -> Self {
+    pub fn for_display(
+        name: String,
+        device: String,
+        display: VcpWriter,
+        curve: PiecewiseLinear,
+    ) -> Self {
         MonitorState {
+            name,
+            device,
             display,
-            curve,
-            target: 0,
-            brightness: 0,
+            learner: None,
+            transition: TransitionConfig::default(),
+            hysteresis: Hysteresis::default(),
+            offset: 0,
+            features: vec![VcpFeature::new(BRIGHTNESS, curve)],
+            night_features: Vec::new(),
+            is_night: None,
+            user_offset: 0,
+            learned_new_sample: false,
+            health: Health::Ok,
+            health_changed: false,
+            retry_at: Instant::now(),
+            closed: false,
         }
     }
 
-    /// Set monitor brightness to the given percentage unconditionally.
-    fn set_brightness(&mut self, pct: u16) -> Result<(), anyhow::Error> {
-        let pct = pct.clamp(0, 100);
+    /// Use the given transition settings instead of the default ones.
+    pub fn with_transition(mut self, transition: TransitionConfig) -> Self {
+        self.transition = transition;
+        self
+    }
+
+    /// Extend the brightness range by `range`, for a display that dims in software below 0%. The
+    /// brightness curve must be shifted up by the same amount.
+    pub fn with_extended_dimming(mut self, range: u16) -> Self {
+        self.features[0].max = 100 + range;
+        self
+    }
 
-        self.display.set_vcp_value(0x10, pct).anyhow()?;
+    /// Shift the brightness from the curve by `offset`, then keep it between `min` and `max`.
+    pub fn with_limits(mut self, min: Option<u16>, max: Option<u16>, offset: i32) -> Self {
+        let brightness = &mut self.features[0];
+        brightness.max = max.map_or(brightness.max, |max| max.min(brightness.max));
+        // Never above the maximum, which would make the limits contradict each other
+        brightness.min = min.unwrap_or(0).min(brightness.max);
+        brightness.offset = offset + self.user_offset;
+        self.offset = offset;
+        self
+    }
 
-        self.brightness = pct;
+    /// Interpret the brightness curve as luminance in nits, converting it to brightness with the given
+    /// profile.
+    pub fn with_luminance(mut self, luminance: LuminanceProfile) -> Self {
+        self.features[0].luminance = Some(luminance);
+        self
+    }
+
+    /// Set the brightness offset chosen at runtime, which is added to the configured one. The
+    /// brightness moves to the new target on the next update.
+    pub fn set_user_offset(&mut self, user_offset: i32) {
+        if user_offset == self.user_offset {
+            return;
+        }
+        println!("{0}: brightness offset {user_offset:+}", self.name);
+        self.user_offset = user_offset;
+
+        let brightness = &mut self.features[0];
+        brightness.offset = self.offset + user_offset;
+        // Apply the change right away rather than waiting for the lux to change
+        brightness.hysteresis = HysteresisState::default();
+    }
+
+    /// Use the given hysteresis policy instead of the default one.
+    pub fn with_hysteresis(mut self, hysteresis: Hysteresis) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Time to wait between updates while values are still moving towards their targets.
+    pub fn transition_interval(&self) -> Duration {
+        Duration::from_millis(self.transition.interval_ms)
+    }
+
+    /// Also control the VCP feature with the given code, following the given curve.
+    pub fn with_feature(mut self, code: u8, curve: PiecewiseLinear) -> Result<Self, anyhow::Error> {
+        self.check_unused_feature(code)?;
+        self.features.push(VcpFeature::new(code, curve));
+        Ok(self)
+    }
+
+    /// Also switch the VCP feature with the given code between day and night values.
+    pub fn with_night_feature(
+        mut self,
+        code: u8,
+        day: u16,
+        night: u16,
+    ) -> Result<Self, anyhow::Error> {
+        self.check_unused_feature(code)?;
+        self.night_features.push(NightFeature { code, day, night });
+        Ok(self)
+    }
+
+    /// Make sure a feature is only controlled one way.
+    fn check_unused_feature(&self, code: u8) -> Result<(), anyhow::Error> {
+        if self.features.iter().any(|f| f.code == code)
+            || self.night_features.iter().any(|f| f.code == code)
+        {
+            anyhow::bail!(
+                "VCP feature {code:#04x} configured more than once for {0}",
+                self.name
+            );
+        }
         Ok(())
     }
 
-    /// Set monitor brightness based on the given lux value unconditionally. Used for initialization.
-    pub fn set_brightness_for_lux(&mut self, lux: u32) -> Result<(), anyhow::Error> {
-        let target = self.curve.eval(lux) as u16;
-        println!("setting initial brightness: lux={lux}, setting={target}");
-        self.set_brightness(target)
+    /// Enable learning from manual brightness adjustments. The brightness curve is replaced by the
+    /// learner's curve.
+    pub fn learn_from(mut self, learner: CurveLearner) -> Result<Self, anyhow::Error> {
+        self.features[0]
+            .replace_curve(learner.curve())
+            .ok_or_else(|| {
+                anyhow::anyhow!("Invalid learned brightness curve for {0}", self.name)
+            })?;
+        self.learner = Some(learner);
+        Ok(self)
     }
 
-    /// Calculate a change in brightness target.
-    ///
-    /// Round to the multiple of `ROUND_TO_NEAREST` that is closest to current value.
+    /// Name of the display, in the form "manufacturer model serial connector", as its state is saved
+    /// under (see `Display::state_key`).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Physical path of the display, e.g. "i2c-6".
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// Whether the monitor is controlled over DDC/CI.
+    pub fn uses_ddc(&self) -> bool {
+        self.display.uses_ddc()
+    }
+
+    /// Close the connection to the display, e.g. before redetecting displays. Reads and writes fail
+    /// until it is reopened.
+    pub fn close(&mut self) {
+        drop(self.display.replace_display(Box::new(Closed)));
+        self.closed = true;
+    }
+
+    /// Continue with a new connection to the same display, keeping the monitor's state.
+    pub fn reopen(&mut self, display: Box<dyn Backend>) {
+        self.display.replace_display(display);
+        self.closed = false;
+    }
+
+    /// Whether the display was closed and not reopened yet.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Brightness the monitor is set to for the given lux value, before hysteresis and transitions.
+    pub fn brightness_for_lux(&self, lux: f64) -> u16 {
+        self.features[0].target_for_lux(lux)
+    }
+
+    /// Counters of reads and writes to this monitor.
+    pub fn write_stats(&self) -> &WriteStats {
+        self.display.stats()
+    }
+
+    /// Current health and counters, for status reporting.
+    pub fn status(&self) -> MonitorStatus {
+        MonitorStatus {
+            health: self.health.clone(),
+            stats: *self.display.stats(),
+        }
+    }
+
+    /// Whether the health changed since the last call.
+    pub fn take_health_changed(&mut self) -> bool {
+        std::mem::take(&mut self.health_changed)
+    }
+
+    /// Mark the monitor as degraded: it will be skipped until it is retried after
+    /// `DEGRADED_RETRY_INTERVAL`.
+    pub fn mark_degraded(&mut self, err: anyhow::Error) {
+        eprintln!(
+            "{0}: marking degraded, retrying in {1}s: {err:#}",
+            self.name,
+            Self::DEGRADED_RETRY_INTERVAL.as_secs()
+        );
+        self.health = Health::Degraded(format!("{err:#}"));
+        self.health_changed = true;
+        self.retry_at = Instant::now() + Self::DEGRADED_RETRY_INTERVAL;
+    }
+
+    /// Fail if writes to the monitor have failed too many times in a row.
+    fn check_failures(&self) -> Result<(), anyhow::Error> {
+        if self.display.too_many_failures() {
+            anyhow::bail!(
+                "Too many consecutive failures writing to {0}: {1:?}",
+                self.name,
+                self.display.stats()
+            );
+        }
+        Ok(())
+    }
+
+    /// If a new sample was learned since the last call, return all the samples learned for this monitor.
+    pub fn take_learned_samples(&mut self) -> Option<Vec<(f64, u32)>> {
+        if !std::mem::take(&mut self.learned_new_sample) {
+            return None;
+        }
+        self.learner.as_ref().map(|l| l.samples().to_vec())
+    }
+
+    /// Set all configured features based on the given lux value unconditionally. Used for
+    /// initialization.
+    pub fn set_for_lux(&mut self, lux: f64) -> Result<(), anyhow::Error> {
+        for feature in &mut self.features {
+            feature.set_for_lux(&mut self.display, lux)?;
+        }
+        Ok(())
+    }
+
+    /// Switch night features to their night or day values, if they aren't already.
     ///
-    /// This prevents the target from moving too erratically, reducing how often we make updates
-    /// oscillating over a small range
-    fn new_target_brightness(cur: u16, new: u16) -> u16 {
-        let round_to = if new > 20 { Self::ROUND_TO_NEAREST } else { 2 };
-
-        if new == cur {
-            new
-        } else if new < cur {
-            (new + round_to - 1) / round_to * round_to
-        } else {
-            new / round_to * round_to
+    /// Failed writes are retried on the next call. Only returns an error if there were too many
+    /// consecutive failures.
+    pub fn set_night(&mut self, is_night: bool) -> Result<(), anyhow::Error> {
+        if self.night_features.is_empty() || self.is_night == Some(is_night) {
+            return Ok(());
         }
+
+        println!(
+            "{0}: switching to {1} mode",
+            self.name,
+            if is_night { "night" } else { "day" }
+        );
+        for f in &self.night_features {
+            let value = if is_night { f.night } else { f.day };
+            if let Err(err) = self.display.set(f.code, value) {
+                eprintln!("{0}: {err:#}", self.name);
+                return self.check_failures();
+            }
+        }
+
+        self.is_night = Some(is_night);
+        Ok(())
     }
 
-    /// Update monitor brightness for the given lux value.
+    /// Check whether the brightness was changed by someone else since we last set it, e.g. using the
+    /// monitor's buttons. If so, learn the new (lux, brightness) pair and refit the curve.
     ///
-    /// Returns true if new brightness value does not match the target, false otherwise.
-    pub fn update_brightness(&mut self, lux: u32) -> Result<bool, anyhow::Error> {
-        let cur = self.brightness;
+    /// Returns true if a manual adjustment was detected.
+    fn detect_manual_adjustment(&mut self, lux: f64) -> Result<bool, anyhow::Error> {
+        let Some(learner) = &mut self.learner else {
+            return Ok(false);
+        };
 
-        self.target = Self::new_target_brightness(cur, self.curve.eval(lux) as u16);
-        let target = self.target;
+        // Only read the brightness back once it has settled, rather than while still writing it
+        let brightness = &mut self.features[0];
+        if brightness.value != brightness.target || brightness.transition.is_some() {
+            return Ok(false);
+        }
+        let actual = brightness.get(&mut self.display)?;
+        if actual == brightness.value {
+            return Ok(false);
+        }
 
-        let change = target as i32 - cur as i32;
+        println!(
+            "{0}: manual adjustment detected: lux={lux:.1}, expected={1}, actual={actual}",
+            self.name, brightness.value
+        );
+        // Learn the curve without the offset, which is applied on top of it
+        let mut sample = (actual as i32 - brightness.offset).max(0) as u32;
+        if let Some(luminance) = &brightness.luminance {
+            sample = luminance.nits(sample);
+        }
+        learner.add_sample(lux, sample);
+        brightness.replace_curve(learner.curve()).ok_or_else(|| {
+            anyhow::anyhow!("Invalid learned brightness curve for {0}", self.name)
+        })?;
+        self.learned_new_sample = true;
 
-        let new_b;
-        if i32::abs(change) <= 3 {
-            new_b = target;
-        } else {
-            new_b = if target > cur { cur + 2 } else { cur - 2 };
+        brightness.value = actual;
+        brightness.target = actual;
+        brightness.transition = None;
+        Ok(true)
+    }
+
+    /// Update the monitor for the given lux value and night state, without letting failures escape.
+    ///
+    /// If the monitor fails, it is marked degraded and skipped until the retry interval has passed.
+    /// Then all its features are set from scratch, and it is healthy again if that succeeds. While
+    /// the display is closed, nothing is done.
+    ///
+    /// Returns true if any new value does not match its target, false otherwise.
+    pub fn update_isolated(&mut self, lux: f64, night: Option<bool>) -> bool {
+        if self.closed {
+            return false;
         }
+        if let Health::Degraded(_) = self.health {
+            if Instant::now() < self.retry_at {
+                return false;
+            }
 
-        if new_b != cur {
-            println!("lux={lux}, target={target}, setting={new_b}");
-            self.set_brightness(new_b)?;
+            self.is_night = None;
+            if let Err(err) = self.set_for_lux(lux) {
+                self.mark_degraded(err);
+                return false;
+            }
+            println!("{0}: recovered", self.name);
+            self.health = Health::Ok;
+            self.health_changed = true;
+        }
 
-            Ok(new_b != target)
-        } else {
-            Ok(false)
+        let res = self.update(lux).and_then(|off_target| {
+            if let Some(night) = night {
+                self.set_night(night)?;
+            }
+            Ok(off_target)
+        });
+        res.unwrap_or_else(|err| {
+            self.mark_degraded(err);
+            false
+        })
+    }
+
+    /// Update all configured features for the given lux value. Each feature moves towards its own
+    /// target independently.
+    ///
+    /// Failed writes are logged and retried on the next update. Only returns an error if there were
+    /// too many consecutive failures.
+    ///
+    /// Returns true if any new value does not match its target, false otherwise.
+    pub fn update(&mut self, lux: f64) -> Result<bool, anyhow::Error> {
+        // Don't fight the user: the learned curve now matches what they chose
+        let skip_brightness = self.detect_manual_adjustment(lux).unwrap_or_else(|err| {
+            eprintln!(
+                "{0}: failed to check for manual adjustment: {err:#}",
+                self.name
+            );
+            false
+        });
+
+        let mut off_target = false;
+        for feature in &mut self.features {
+            if skip_brightness && feature.code == BRIGHTNESS {
+                continue;
+            }
+            match feature.update(&mut self.display, lux, &self.transition, &self.hysteresis) {
+                Ok(feature_off_target) => off_target |= feature_off_target,
+                Err(err) => {
+                    eprintln!("{0}: {err:#}", self.name);
+                    off_target = true;
+                }
+            }
         }
+
+        self.check_failures()?;
+        Ok(off_target)
     }
 }
 
-#[test]
-fn test_new_target_brightness() {
-    for new in 46..=54 {
-        assert_eq!(50, MonitorState::new_target_brightness(50, new));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WritePolicy;
+    use crate::test_util::FakeBackend;
+
+    fn monitor(backend: &FakeBackend, curve: Vec<(f64, u32)>) -> MonitorState {
+        MonitorState::for_display(
+            "GBT G27Q 2323".to_string(),
+            "i2c-6".to_string(),
+            VcpWriter::new(Box::new(backend.clone()), WritePolicy::default()),
+            PiecewiseLinear::from_steps(curve).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_new_target_brightness() {
+        let policy = Hysteresis::default();
+        let new_target_brightness = |cur, new| {
+            HysteresisState::default().new_target(&policy, cur, cur, new, 0.0, Instant::now())
+        };
+
+        for new in 46..=54 {
+            assert_eq!(50, new_target_brightness(50, new));
+        }
+        assert_eq!(50, new_target_brightness(0, 54));
+        assert_eq!(50, new_target_brightness(100, 46));
+        assert_eq!(55, new_target_brightness(50, 55));
+        assert_eq!(45, new_target_brightness(50, 45));
+
+        for new in 51..=59 {
+            assert_eq!(55, new_target_brightness(55, new));
+        }
+    }
+
+    #[test]
+    fn offsets_and_limits() {
+        let backend = FakeBackend::default();
+        let mut m =
+            monitor(&backend, vec![(0.0, 10), (250.0, 100)]).with_limits(Some(20), Some(90), 5);
+        m.set_user_offset(10);
+        assert_eq!(25, m.brightness_for_lux(0.0));
+        assert_eq!(70, m.brightness_for_lux(125.0));
+        assert_eq!(90, m.brightness_for_lux(250.0));
+
+        m.set_user_offset(-20);
+        assert_eq!(20, m.brightness_for_lux(0.0));
+    }
+
+    #[test]
+    fn degraded_and_recovered() {
+        let backend = FakeBackend::default();
+        let policy = WritePolicy {
+            retries: 0,
+            max_consecutive_failures: 1,
+            ..Default::default()
+        };
+        let mut m = MonitorState::for_display(
+            "GBT G27Q 2323".to_string(),
+            "i2c-6".to_string(),
+            VcpWriter::new(Box::new(backend.clone()), policy),
+            PiecewiseLinear::from_steps(vec![(0.0, 10), (250.0, 100)]).unwrap(),
+        );
+
+        *backend.failing_writes.lock().unwrap() = 1;
+        assert!(!m.update_isolated(100.0, None));
+        assert!(matches!(m.status().health, Health::Degraded(_)));
+        assert!(m.take_health_changed());
+        assert!(!m.take_health_changed());
+
+        // Skipped until the retry interval has passed
+        assert!(!m.update_isolated(100.0, None));
+        assert_eq!(0, *backend.writes.lock().unwrap());
+
+        // Then set from scratch
+        m.retry_at = Instant::now();
+        m.update_isolated(100.0, None);
+        assert_eq!(Health::Ok, m.status().health);
+        assert!(m.take_health_changed());
+        assert_eq!(46, backend.values.lock().unwrap()[&BRIGHTNESS]);
+    }
+
+    #[test]
+    fn closed_and_reopened() {
+        let backend = FakeBackend::default();
+        let mut m = monitor(&backend, vec![(0.0, 10), (250.0, 100)]);
+        m.set_for_lux(0.0).unwrap();
+
+        // Skipped while closed, without counting failures
+        m.close();
+        assert!(m.is_closed());
+        assert!(!m.update_isolated(100.0, None));
+        assert_eq!(Health::Ok, m.status().health);
+        assert_eq!(10, backend.values.lock().unwrap()[&BRIGHTNESS]);
+
+        m.reopen(Box::new(backend.clone()));
+        assert!(!m.is_closed());
+        m.update_isolated(100.0, None);
+        assert_ne!(10, backend.values.lock().unwrap()[&BRIGHTNESS]);
     }
-    assert_eq!(50, MonitorState::new_target_brightness(0, 54));
-    assert_eq!(50, MonitorState::new_target_brightness(100, 46));
-    assert_eq!(55, MonitorState::new_target_brightness(50, 55));
-    assert_eq!(45, MonitorState::new_target_brightness(50, 45));
 
-    for new in 51..=59 {
-        assert_eq!(55, MonitorState::new_target_brightness(55, new));
+    #[test]
+    fn min_above_max() {
+        // The minimum is limited to what the monitor can do
+        let backend = FakeBackend::default();
+        let mut m =
+            monitor(&backend, vec![(0.0, 10), (250.0, 100)]).with_limits(Some(150), None, 0);
+        m.set_for_lux(50.0).unwrap();
+        m.update(50.0).unwrap();
+        assert_eq!(100, backend.values.lock().unwrap()[&BRIGHTNESS]);
     }
 }
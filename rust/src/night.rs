@@ -0,0 +1,168 @@
+/// Decides whether it is currently night, either from a fixed schedule or from the position of the sun.
+///
+/// Sunrise/sunset is computed locally using the sunrise equation:
+///     https://en.wikipedia.org/wiki/Sunrise_equation
+use crate::config::NightSchedule;
+
+use chrono::{Local, Timelike};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Julian date of the unix epoch
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// Julian date of the J2000 epoch
+const J2000: f64 = 2451545.0;
+
+/// Sunrise and sunset for a single day
+#[derive(Debug, PartialEq)]
+enum SunDay {
+    /// The sun rises and sets at these times (unix timestamps)
+    RiseSet(f64, f64),
+    /// The sun doesn't set
+    PolarDay,
+    /// The sun doesn't rise
+    PolarNight,
+}
+
+/// Whether it is currently night according to the schedule.
+pub fn is_night(schedule: &NightSchedule) -> bool {
+    match *schedule {
+        NightSchedule::Fixed(start, end) => {
+            let now = Local::now();
+            in_fixed_range(now.hour() * 60 + now.minute(), start, end)
+        }
+        NightSchedule::Sun(latitude, longitude) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            is_sun_down(now, latitude, longitude)
+        }
+    }
+}
+
+/// Whether the minute of the day is within the range [start, end), where start and end are (hour, minute).
+/// The range wraps around midnight if the end is before the start.
+fn in_fixed_range(minute_of_day: u32, start: (u8, u8), end: (u8, u8)) -> bool {
+    let start = start.0 as u32 * 60 + start.1 as u32;
+    let end = end.0 as u32 * 60 + end.1 as u32;
+
+    if start <= end {
+        start <= minute_of_day && minute_of_day < end
+    } else {
+        minute_of_day >= start || minute_of_day < end
+    }
+}
+
+/// Whether the sun is down at the given time (unix timestamp) and location (degrees, east positive).
+fn is_sun_down(unix_time: f64, latitude: f64, longitude: f64) -> bool {
+    match sun_times(unix_time, latitude, longitude) {
+        SunDay::RiseSet(rise, set) => unix_time < rise || unix_time >= set,
+        SunDay::PolarDay => false,
+        SunDay::PolarNight => true,
+    }
+}
+
+/// Compute sunrise and sunset of the day whose solar noon is closest to the given time.
+fn sun_times(unix_time: f64, latitude: f64, longitude: f64) -> SunDay {
+    let jd = unix_time / 86400.0 + UNIX_EPOCH_JD;
+
+    // Day number such that solar noon is within half a day of the given time
+    let n = (jd - J2000 - 0.0008 + longitude / 360.0).round();
+
+    // Mean solar time
+    let j_star = n + 0.0008 - longitude / 360.0;
+
+    // Solar mean anomaly
+    let m = (357.5291 + 0.98560028 * j_star)
+        .rem_euclid(360.0)
+        .to_radians();
+
+    // Equation of the center
+    let c = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+
+    // Ecliptic longitude
+    let lambda = (m.to_degrees() + c + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+
+    // Solar transit
+    let j_transit = J2000 + j_star + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+
+    // Declination of the sun
+    let sin_decl = lambda.sin() * 23.4397f64.to_radians().sin();
+    let cos_decl = sin_decl.asin().cos();
+
+    // Hour angle, accounting for atmospheric refraction and the size of the sun
+    let lat = latitude.to_radians();
+    let cos_omega =
+        ((-0.833f64).to_radians().sin() - lat.sin() * sin_decl) / (lat.cos() * cos_decl);
+    if cos_omega < -1.0 {
+        return SunDay::PolarDay;
+    } else if cos_omega > 1.0 {
+        return SunDay::PolarNight;
+    }
+    let omega = cos_omega.acos().to_degrees();
+
+    let to_unix = |jd: f64| (jd - UNIX_EPOCH_JD) * 86400.0;
+    SunDay::RiseSet(
+        to_unix(j_transit - omega / 360.0),
+        to_unix(j_transit + omega / 360.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-06-21 12:00 UTC
+    const SUMMER_SOLSTICE: f64 = 1718971200.0;
+    /// 2024-12-21 12:00 UTC
+    const WINTER_SOLSTICE: f64 = 1734782400.0;
+
+    #[test]
+    fn fixed_range() {
+        // Same day
+        assert!(!in_fixed_range(0, (1, 30), (6, 0)));
+        assert!(in_fixed_range(90, (1, 30), (6, 0)));
+        assert!(in_fixed_range(359, (1, 30), (6, 0)));
+        assert!(!in_fixed_range(360, (1, 30), (6, 0)));
+
+        // Wrapping around midnight
+        assert!(!in_fixed_range(1259, (21, 0), (7, 0)));
+        assert!(in_fixed_range(1260, (21, 0), (7, 0)));
+        assert!(in_fixed_range(0, (21, 0), (7, 0)));
+        assert!(in_fixed_range(419, (21, 0), (7, 0)));
+        assert!(!in_fixed_range(420, (21, 0), (7, 0)));
+        assert!(!in_fixed_range(720, (21, 0), (7, 0)));
+    }
+
+    #[test]
+    fn greenwich_summer_solstice() {
+        // Sunrise 03:43 UTC, sunset 20:21 UTC
+        let SunDay::RiseSet(rise, set) = sun_times(SUMMER_SOLSTICE, 51.48, 0.0) else {
+            panic!("expected sunrise and sunset");
+        };
+        assert!((rise - (SUMMER_SOLSTICE - 8.0 * 3600.0 - 17.0 * 60.0)).abs() < 300.0);
+        assert!((set - (SUMMER_SOLSTICE + 8.0 * 3600.0 + 21.0 * 60.0)).abs() < 300.0);
+
+        assert!(is_sun_down(SUMMER_SOLSTICE - 9.0 * 3600.0, 51.48, 0.0));
+        assert!(!is_sun_down(SUMMER_SOLSTICE, 51.48, 0.0));
+        assert!(is_sun_down(SUMMER_SOLSTICE + 9.0 * 3600.0, 51.48, 0.0));
+    }
+
+    #[test]
+    fn western_longitude() {
+        // Toronto: noon UTC is early morning, after sunrise at 09:36 UTC in summer
+        assert!(!is_sun_down(SUMMER_SOLSTICE, 43.65, -79.38));
+        // ... and before sunrise at 12:50 UTC in winter
+        assert!(is_sun_down(WINTER_SOLSTICE, 43.65, -79.38));
+    }
+
+    #[test]
+    fn polar() {
+        assert_eq!(SunDay::PolarDay, sun_times(SUMMER_SOLSTICE, 80.0, 0.0));
+        assert_eq!(SunDay::PolarNight, sun_times(WINTER_SOLSTICE, 80.0, 0.0));
+        assert!(!is_sun_down(SUMMER_SOLSTICE, 80.0, 0.0));
+        assert!(is_sun_down(WINTER_SOLSTICE, 80.0, 0.0));
+    }
+}
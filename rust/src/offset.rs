@@ -0,0 +1,100 @@
+/// Brightness offsets chosen at runtime with the `offset` command, e.g. to make one monitor a bit
+/// brighter without changing its curve. The daemon applies them on top of the configured offsets.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Offset of each display, as stored in the state file.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Offsets {
+    /// Offsets keyed by the display's "manufacturer model serial connector" string.
+    pub displays: BTreeMap<String, i32>,
+}
+
+impl Offsets {
+    pub fn read_from_file<P: AsRef<Path>>(file: P) -> Result<Self, anyhow::Error> {
+        Ok(ron::de::from_reader(BufReader::new(File::open(file)?))?)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, file: P) -> Result<(), anyhow::Error> {
+        let format_opts = ron::ser::PrettyConfig::new().indentor("  ");
+        ron::Options::default().to_io_writer_pretty(File::create(file)?, self, format_opts)?;
+        Ok(())
+    }
+
+    /// Offset of the given display, 0 if none was set.
+    pub fn get(&self, name: &str) -> i32 {
+        self.displays.get(name).copied().unwrap_or(0)
+    }
+
+    /// Set the offset of the given display. Offsets of 0 aren't stored.
+    pub fn set(&mut self, name: &str, offset: i32) {
+        if offset == 0 {
+            self.displays.remove(name);
+        } else {
+            self.displays.insert(name.to_string(), offset);
+        }
+    }
+}
+
+/// Find the display named by `query` among `names`: either its exact name, or a part of the name
+/// (ignoring case) that only matches one display.
+pub fn find_display<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    query: &str,
+) -> Result<&'a str, anyhow::Error> {
+    let names: Vec<&str> = names.into_iter().collect();
+    if let Some(&name) = names.iter().find(|&&name| name == query) {
+        return Ok(name);
+    }
+
+    let lower = query.to_lowercase();
+    let matches: Vec<&str> = names
+        .into_iter()
+        .filter(|name| name.to_lowercase().contains(&lower))
+        .collect();
+    match matches[..] {
+        [name] => Ok(name),
+        [] => anyhow::bail!("No monitor matches {query:?}"),
+        _ => anyhow::bail!("{query:?} matches more than one monitor: {matches:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_and_set() {
+        let mut offsets = Offsets::default();
+        assert_eq!(0, offsets.get("GBT G27Q 2323"));
+
+        offsets.set("GBT G27Q 2323", -5);
+        assert_eq!(-5, offsets.get("GBT G27Q 2323"));
+
+        offsets.set("GBT G27Q 2323", 0);
+        assert!(offsets.displays.is_empty());
+    }
+
+    #[test]
+    fn find() {
+        let names = [
+            "GBT G27Q 2323",
+            "GBT G27QC 1111",
+            "backlight intel_backlight",
+        ];
+        assert_eq!(
+            "GBT G27Q 2323",
+            find_display(names, "GBT G27Q 2323").unwrap()
+        );
+        assert_eq!("GBT G27QC 1111", find_display(names, "g27qc").unwrap());
+        assert_eq!(
+            "backlight intel_backlight",
+            find_display(names, "backlight").unwrap()
+        );
+        assert!(find_display(names, "G27Q").is_err());
+        assert!(find_display(names, "DELL").is_err());
+    }
+}
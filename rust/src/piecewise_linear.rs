@@ -1,35 +1,95 @@
-/// Represents a piece-wise linear function and can be evaluated at a point.
+/// Represents a piece-wise linear function and can be evaluated at a point. It can also be
+/// interpolated in other ways between the points, including as a monotone cubic spline.
+use crate::config::Interpolation;
 
-#[derive(Debug)]
-pub struct PiecewiseLinear {
-    curve: Vec<(u32, u32)>,
+/// Numeric types a curve can take as input or give as output. Interpolation is done in `f64`.
+pub trait CurveValue: Copy + PartialOrd {
+    fn to_f64(self) -> f64;
+
+    /// Convert back from `f64`, rounding to the nearest value and limiting it to the type's range.
+    fn from_f64(value: f64) -> Self;
+}
+
+impl CurveValue for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+macro_rules! integer_curve_value {
+    ($($t:ty),*) => {$(
+        impl CurveValue for $t {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                // Casts from float saturate at the bounds of the type
+                value.round() as $t
+            }
+        }
+    )*};
 }
 
-/*
-TODO:
- - make generic over input/output type?
-*/
+integer_curve_value!(u16, u32, i32);
+
+/// A curve from input values of type `X` (by default lux) to output values of type `Y`.
+#[derive(Debug)]
+pub struct PiecewiseLinear<X = f64, Y = u32> {
+    curve: Vec<(X, Y)>,
+    interpolation: Interpolation,
+    /// Slope at each point, for `Interpolation::MonotoneCubic`
+    slopes: Vec<f64>,
+}
 
-impl PiecewiseLinear {
+impl<X: CurveValue, Y: CurveValue> PiecewiseLinear<X, Y> {
     /// Initialize a piecewise linear function from a vector of (input, output) pairs
     ///
     /// Preconditions:
     ///  - There should not be duplicate input values
-    ///  - The input should not be empty
+    ///  - The input should not be empty, or contain NaN
     ///  - Ideally they should be ordered but this is not necessary
-    pub fn from_steps(mut curve_steps: Vec<(u32, u32)>) -> Option<Self> {
-        curve_steps.sort();
-
+    ///
+    /// Returns None if the preconditions aren't met.
+    pub fn from_steps(mut curve_steps: Vec<(X, Y)>) -> Option<Self> {
         // Invalid inputs:
-        if curve_steps.is_empty() {
+        if curve_steps.is_empty() || curve_steps.iter().any(|p| p.0.partial_cmp(&p.0).is_none()) {
+            return None;
+        }
+
+        curve_steps.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        if curve_steps.windows(2).any(|w| w[0].0 == w[1].0) {
             return None;
         }
+        Some(PiecewiseLinear {
+            curve: curve_steps,
+            interpolation: Interpolation::Linear,
+            slopes: Vec::new(),
+        })
+    }
+
+    /// Interpolate between the points in the given way, instead of linearly.
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self.slopes = match interpolation {
+            Interpolation::MonotoneCubic => monotone_slopes(&self.curve),
+            _ => Vec::new(),
+        };
+        self
+    }
 
-        Some(PiecewiseLinear { curve: curve_steps })
+    /// How the function is interpolated between its points.
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
     }
 
-    /// Evaluate the piecewise linear function at a given point.
-    pub fn eval(&self, x: u32) -> u32 {
+    /// Evaluate the piecewise linear function at a given point. For integer outputs, the result is
+    /// rounded to the nearest value.
+    pub fn eval(&self, x: X) -> Y {
         // Interpolate based on the piece-wise linear curve
         match self.curve.iter().position(|p| p.0 > x) {
             // boundary: x >= all points in the curve, take last y value
@@ -40,22 +100,97 @@ impl PiecewiseLinear {
             // x is somewhere within the curve,
             Some(i) => {
                 let (lx, ly) = self.curve[i - 1];
-                let (rd, ry) = self.curve[i];
+                let (rx, ry) = self.curve[i];
+                if x == lx {
+                    return ly;
+                }
+                let (x, lx, rx) = (x.to_f64(), lx.to_f64(), rx.to_f64());
 
-                let b = ly as f64 + (ry - ly) as f64 * (x - lx) as f64 / (rd - lx) as f64;
-                b as u32
+                // Fraction of the way from the left point to the right one
+                let t = match self.interpolation {
+                    Interpolation::LogLux => (x.ln_1p() - lx.ln_1p()) / (rx.ln_1p() - lx.ln_1p()),
+                    _ => (x - lx) / (rx - lx),
+                };
+
+                let (ly, ry) = (ly.to_f64(), ry.to_f64());
+                let b = match self.interpolation {
+                    Interpolation::Gamma(gamma) => {
+                        // Interpolate in gamma-encoded space, which is closer to how brightness is perceived
+                        let ly = ly.powf(1.0 / gamma);
+                        let ry = ry.powf(1.0 / gamma);
+                        (ly + (ry - ly) * t).powf(gamma)
+                    }
+                    Interpolation::MonotoneCubic => {
+                        // Cubic Hermite spline with the precomputed slopes
+                        let h = rx - lx;
+                        let (lm, rm) = (self.slopes[i - 1] * h, self.slopes[i] * h);
+                        let (t2, t3) = (t * t, t * t * t);
+                        ly + (ry - ly) * (3.0 * t2 - 2.0 * t3)
+                            + (t3 - 2.0 * t2 + t) * lm
+                            + (t3 - t2) * rm
+                    }
+                    _ => ly + (ry - ly) * t,
+                };
+                Y::from_f64(b)
             }
         }
     }
 }
 
+/// Slopes at each point for a monotone cubic spline through the points, using the Fritsch–Carlson
+/// method: start from the average of the neighbouring segments' slopes, then limit them so the
+/// spline doesn't overshoot where the data is monotone.
+fn monotone_slopes<X: CurveValue, Y: CurveValue>(points: &[(X, Y)]) -> Vec<f64> {
+    if points.len() < 2 {
+        return vec![0.0; points.len()];
+    }
+
+    // Slope of each segment
+    let deltas: Vec<f64> = points
+        .windows(2)
+        .map(|w| (w[1].1.to_f64() - w[0].1.to_f64()) / (w[1].0.to_f64() - w[0].0.to_f64()))
+        .collect();
+
+    let n = points.len();
+    let mut slopes = vec![0.0; n];
+    slopes[0] = deltas[0];
+    slopes[n - 1] = deltas[n - 2];
+    for k in 1..n - 1 {
+        // Flat at local extrema, so the spline doesn't go past them
+        if deltas[k - 1] * deltas[k] > 0.0 {
+            slopes[k] = (deltas[k - 1] + deltas[k]) / 2.0;
+        }
+    }
+
+    for (k, &delta) in deltas.iter().enumerate() {
+        if delta == 0.0 {
+            slopes[k] = 0.0;
+            slopes[k + 1] = 0.0;
+            continue;
+        }
+        let (a, b) = (slopes[k] / delta, slopes[k + 1] / delta);
+        let length = a.hypot(b);
+        if length > 3.0 {
+            slopes[k] = 3.0 * a / length * delta;
+            slopes[k + 1] = 3.0 * b / length * delta;
+        }
+    }
+    slopes
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn empty_curve() {
-        let curve = PiecewiseLinear::from_steps(vec![]);
+        let curve = PiecewiseLinear::<f64, u32>::from_steps(vec![]);
+        assert!(curve.is_none());
+    }
+
+    #[test]
+    fn duplicate_input_curve() {
+        let curve = PiecewiseLinear::from_steps(vec![(0, 10), (50, 30), (50, 40)]);
         assert!(curve.is_none());
     }
 
@@ -73,9 +208,9 @@ mod tests {
         assert_eq!(30, curve.eval(0));
         assert_eq!(30, curve.eval(20));
         assert_eq!(30, curve.eval(50));
-        assert_eq!(42.5 as u32, curve.eval(100));
+        assert_eq!(43, curve.eval(100));
         assert_eq!(55, curve.eval(150));
-        assert_eq!(67.5 as u32, curve.eval(200));
+        assert_eq!(68, curve.eval(200));
         assert_eq!(80, curve.eval(250));
         assert_eq!(80, curve.eval(300));
     }
@@ -119,4 +254,77 @@ mod tests {
             assert_eq!(10 * i, curve.eval(10 * i));
         }
     }
+
+    #[test]
+    fn log_lux_curve() {
+        let curve = PiecewiseLinear::from_steps(vec![(0, 0), (9, 20), (999, 80)])
+            .unwrap()
+            .with_interpolation(Interpolation::LogLux);
+        assert_eq!(0, curve.eval(0));
+        assert_eq!(20, curve.eval(9));
+        // Each order of magnitude of lux is an equal step in brightness
+        assert_eq!(50, curve.eval(99));
+        assert_eq!(80, curve.eval(999));
+        assert_eq!(80, curve.eval(5000));
+    }
+
+    #[test]
+    fn gamma_curve() {
+        let curve = PiecewiseLinear::from_steps(vec![(0, 0), (100, 100)])
+            .unwrap()
+            .with_interpolation(Interpolation::Gamma(2.0));
+        assert_eq!(0, curve.eval(0));
+        // Perceptually, halfway from 0 to 100 is much less than 50
+        assert_eq!(25, curve.eval(50));
+        assert_eq!(100, curve.eval(100));
+
+        // Points are exact even when the gamma doesn't round-trip exactly
+        let curve = PiecewiseLinear::from_steps(vec![(0, 30), (100, 70), (200, 90)])
+            .unwrap()
+            .with_interpolation(Interpolation::Gamma(2.2));
+        assert_eq!(30, curve.eval(0));
+        assert_eq!(70, curve.eval(100));
+    }
+
+    #[test]
+    fn monotone_cubic_curve() {
+        let points = vec![(0, 0), (100, 10), (200, 90), (300, 100)];
+        let curve = PiecewiseLinear::from_steps(points.clone())
+            .unwrap()
+            .with_interpolation(Interpolation::MonotoneCubic);
+        for (x, y) in points {
+            assert_eq!(y, curve.eval(x));
+        }
+
+        // Smooth rather than straight between the points
+        assert!(curve.eval(50) < 5);
+        assert!(curve.eval(250) > 95);
+
+        // No overshoot: never decreases, and stays within the range of the points
+        let values: Vec<u32> = (0..=300).map(|x| curve.eval(x)).collect();
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+        assert!(values.iter().all(|&y| y <= 100));
+
+        // Flat segments stay flat
+        let curve = PiecewiseLinear::from_steps(vec![(0, 20), (100, 50), (200, 50), (300, 80)])
+            .unwrap()
+            .with_interpolation(Interpolation::MonotoneCubic);
+        assert!((100..=200).all(|x| curve.eval(x) == 50));
+    }
+
+    #[test]
+    fn fractional_curve() {
+        // Lux below 1 can be told apart, and the result is rounded
+        let curve: PiecewiseLinear =
+            PiecewiseLinear::from_steps(vec![(0.0, 0), (0.5, 10), (2.0, 40)]).unwrap();
+        assert_eq!(5, curve.eval(0.25));
+        assert_eq!(8, curve.eval(0.375));
+        assert_eq!(20, curve.eval(1.0));
+        assert_eq!(40, curve.eval(2.5));
+
+        let curve = PiecewiseLinear::<f64, f64>::from_steps(vec![(0.0, 0.0), (1.0, 0.5)]).unwrap();
+        assert_eq!(0.05, curve.eval(0.1));
+
+        assert!(PiecewiseLinear::<f64, u32>::from_steps(vec![(f64::NAN, 10)]).is_none());
+    }
 }
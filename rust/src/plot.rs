@@ -0,0 +1,268 @@
+/// Charts of brightness curves, drawn in the terminal or as SVG, for the `plot` command.
+use std::fmt::Write;
+
+/// A curve to plot.
+pub struct Series {
+    pub name: String,
+    /// Value of the curve at the given lux
+    pub value: Box<dyn Fn(f64) -> f64>,
+}
+
+impl Series {
+    fn value(&self, lux: f64) -> f64 {
+        (self.value)(lux)
+    }
+}
+
+/// Lux axis from 0 to `max`, either linear or logarithmic.
+pub struct Axis {
+    pub max: f64,
+    pub log: bool,
+}
+
+impl Axis {
+    /// Position of the given lux on the axis, from 0 to 1.
+    fn position(&self, lux: f64) -> f64 {
+        if self.log {
+            lux.ln_1p() / self.max.ln_1p()
+        } else {
+            lux / self.max
+        }
+    }
+
+    /// Lux at the given position on the axis, from 0 to 1.
+    fn lux_at(&self, position: f64) -> f64 {
+        if self.log {
+            (position * self.max.ln_1p()).exp_m1()
+        } else {
+            position * self.max
+        }
+    }
+}
+
+/// Range of values to show: at least 0 to 100, widened to fit the curves.
+fn value_range(series: &[Series], axis: &Axis, samples: usize) -> (f64, f64) {
+    let mut range = (0.0, 100.0);
+    for s in series {
+        for i in 0..samples {
+            let value = s.value(axis.lux_at(i as f64 / (samples - 1) as f64));
+            range = (f64::min(range.0, value), f64::max(range.1, value));
+        }
+    }
+    range
+}
+
+/// Draw a single curve as a `width` by `height` character chart, with the given lux marked.
+pub fn terminal(
+    series: &Series,
+    axis: &Axis,
+    lux: Option<f64>,
+    width: usize,
+    height: usize,
+) -> String {
+    let (min, max) = value_range(std::slice::from_ref(series), axis, width);
+    let row_of = |value: f64| ((value - min) / (max - min) * (height - 1) as f64).round() as usize;
+    let marker =
+        lux.map(|lux| (axis.position(lux).clamp(0.0, 1.0) * (width - 1) as f64).round() as usize);
+
+    let mut grid = vec![vec![' '; width]; height];
+    if let Some(col) = marker {
+        for row in grid.iter_mut() {
+            row[col] = '|';
+        }
+    }
+    let values = (0..width).map(|col| series.value(axis.lux_at(col as f64 / (width - 1) as f64)));
+    for (col, value) in values.enumerate() {
+        let c = if marker == Some(col) { 'o' } else { '*' };
+        grid[height - 1 - row_of(value)][col] = c;
+    }
+
+    let mut out = String::new();
+    for (i, row) in grid.iter().enumerate() {
+        let label = match i {
+            0 => format!("{max:>5}"),
+            _ if i == height - 1 => format!("{min:>5}"),
+            _ => " ".repeat(5),
+        };
+        let _ = writeln!(out, "{label} |{0}", row.iter().collect::<String>());
+    }
+    let _ = writeln!(out, "{0} +{1}", " ".repeat(5), "-".repeat(width));
+    let scale = if axis.log { "lux, log scale" } else { "lux" };
+    let (left, right) = ("0".to_string(), format!("{0} {scale}", axis.max));
+    let _ = writeln!(
+        out,
+        "{0}  {left}{right:>1$}",
+        " ".repeat(5),
+        width - left.len()
+    );
+    out
+}
+
+/// Escape text for use in SVG.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draw all curves in one SVG image, with a legend and the given lux marked.
+pub fn svg(series: &[Series], axis: &Axis, lux: Option<f64>) -> String {
+    const COLOURS: [&str; 6] = [
+        "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+    ];
+    const SAMPLES: usize = 200;
+    let (width, height, margin) = (640.0, 360.0, 50.0);
+    let (plot_w, plot_h) = (width - 2.0 * margin, height - 2.0 * margin);
+
+    let (min, max) = value_range(series, axis, SAMPLES);
+    let x_of = |position: f64| margin + position * plot_w;
+    let y_of = |value: f64| margin + (1.0 - (value - min) / (max - min)) * plot_h;
+
+    let legend_h = 20.0 * series.len() as f64;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{0}" font-family="sans-serif" font-size="12">"#,
+        height + legend_h
+    );
+    let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    // Axes and their labels
+    let (left, right, top, bottom) = (x_of(0.0), x_of(1.0), y_of(max), y_of(min));
+    let _ = writeln!(
+        out,
+        r#"<polyline points="{left},{top} {left},{bottom} {right},{bottom}" fill="none" stroke="black"/>"#
+    );
+    let _ = writeln!(
+        out,
+        r#"<text x="{0}" y="{top}" text-anchor="end">{max}</text>"#,
+        left - 5.0
+    );
+    let _ = writeln!(
+        out,
+        r#"<text x="{0}" y="{bottom}" text-anchor="end">{min}</text>"#,
+        left - 5.0
+    );
+    let _ = writeln!(out, r#"<text x="{left}" y="{0}">0</text>"#, bottom + 15.0);
+    let scale = if axis.log { "lux, log scale" } else { "lux" };
+    let _ = writeln!(
+        out,
+        r#"<text x="{right}" y="{0}" text-anchor="end">{1} {scale}</text>"#,
+        bottom + 15.0,
+        axis.max
+    );
+
+    if let Some(lux) = lux {
+        let x = x_of(axis.position(lux).clamp(0.0, 1.0));
+        let _ = writeln!(
+            out,
+            r#"<line x1="{x}" y1="{top}" x2="{x}" y2="{bottom}" stroke="gray" stroke-dasharray="4"/>"#
+        );
+        let _ = writeln!(
+            out,
+            r#"<text x="{x}" y="{0}" text-anchor="middle">{lux:.1} lux</text>"#,
+            top - 5.0
+        );
+    }
+
+    for (i, s) in series.iter().enumerate() {
+        let colour = COLOURS[i % COLOURS.len()];
+        let points: Vec<String> = (0..SAMPLES)
+            .map(|j| {
+                let position = j as f64 / (SAMPLES - 1) as f64;
+                let value = s.value(axis.lux_at(position));
+                format!("{0:.1},{1:.1}", x_of(position), y_of(value))
+            })
+            .collect();
+        let _ = writeln!(
+            out,
+            r#"<polyline points="{0}" fill="none" stroke="{colour}" stroke-width="2"/>"#,
+            points.join(" ")
+        );
+
+        let y = height + 20.0 * i as f64;
+        let _ = writeln!(
+            out,
+            r#"<rect x="{margin}" y="{0}" width="12" height="12" fill="{colour}"/>"#,
+            y - 10.0
+        );
+        let _ = writeln!(
+            out,
+            r#"<text x="{0}" y="{y}">{1}</text>"#,
+            margin + 18.0,
+            escape(&s.name)
+        );
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piecewise_linear::PiecewiseLinear;
+
+    fn series() -> Series {
+        let curve = PiecewiseLinear::from_steps(vec![(0.0, 10), (250.0, 100)]).unwrap();
+        Series {
+            name: "Model(\"GBT\", \"G27Q\")".to_string(),
+            value: Box::new(move |lux| curve.eval(lux) as f64),
+        }
+    }
+
+    #[test]
+    fn axis() {
+        let linear = Axis {
+            max: 1000.0,
+            log: false,
+        };
+        assert_eq!(0.25, linear.position(250.0));
+        assert_eq!(250.0, linear.lux_at(0.25));
+
+        let log = Axis {
+            max: 999.0,
+            log: true,
+        };
+        assert!((log.position(9.0) - 1.0 / 3.0).abs() < 1e-9);
+        assert!((log.lux_at(log.position(123.0)) - 123.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn terminal_chart() {
+        let axis = Axis {
+            max: 500.0,
+            log: false,
+        };
+        let chart = terminal(&series(), &axis, Some(250.0), 11, 5);
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(
+            vec![
+                "  100 |     o*****",
+                "      |   **|     ",
+                "      |  *  |     ",
+                "      | *   |     ",
+                "    0 |*    |     ",
+                "      +-----------",
+                "       0   500 lux",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn svg_image() {
+        let axis = Axis {
+            max: 500.0,
+            log: true,
+        };
+        let image = svg(&[series()], &axis, Some(100.0));
+        assert!(image.starts_with("<svg "));
+        assert!(image.ends_with("</svg>\n"));
+        assert_eq!(2, image.matches("<polyline").count());
+        assert!(image.contains("100.0 lux"));
+        assert!(image.contains("Model(&quot;GBT&quot;, &quot;G27Q&quot;)"));
+        assert!(image.contains("500 lux, log scale"));
+    }
+}
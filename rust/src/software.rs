@@ -0,0 +1,239 @@
+/// Software brightness for monitors without DDC/CI, applied by the compositor through KDE's
+/// `org.kde.ScreenBrightness` D-Bus service.
+use crate::backend::Backend;
+use crate::monitor::BRIGHTNESS;
+
+use anyhow::Context;
+use zbus::blocking::Connection;
+use zbus::proxy::CacheProperties;
+
+const SERVICE: &str = "org.kde.ScreenBrightness";
+const PATH: &str = "/org/kde/ScreenBrightness";
+
+/// Don't show the on-screen brightness indicator for changes we make
+const SUPPRESS_INDICATOR: u32 = 0x1;
+
+#[zbus::proxy(
+    interface = "org.kde.ScreenBrightness",
+    default_service = "org.kde.ScreenBrightness",
+    default_path = "/org/kde/ScreenBrightness"
+)]
+trait ScreenBrightness {
+    #[zbus(property, name = "DisplaysDBusNames")]
+    fn displays_dbus_names(&self) -> zbus::Result<Vec<String>>;
+}
+
+#[zbus::proxy(
+    interface = "org.kde.ScreenBrightness.Display",
+    default_service = "org.kde.ScreenBrightness"
+)]
+trait ScreenBrightnessDisplay {
+    #[zbus(property)]
+    fn label(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn brightness(&self) -> zbus::Result<i32>;
+
+    #[zbus(property)]
+    fn max_brightness(&self) -> zbus::Result<i32>;
+
+    fn set_brightness(&self, brightness: i32, flags: u32) -> zbus::Result<()>;
+}
+
+/// A display whose brightness can be controlled by the compositor.
+#[derive(Debug, Clone)]
+pub struct SoftwareDisplay {
+    /// Human readable name of the display, as shown in the desktop's brightness settings
+    pub label: String,
+    /// Name of the display's D-Bus object, e.g. "display0"
+    pub name: String,
+    conn: Connection,
+}
+
+impl SoftwareDisplay {
+    /// List the displays of the session's compositor. If there is no session bus or the service
+    /// isn't running, there are no software displays.
+    pub fn detect() -> Result<Vec<Self>, anyhow::Error> {
+        let Ok(conn) = Connection::session() else {
+            return Ok(vec![]);
+        };
+        let dbus = zbus::blocking::fdo::DBusProxy::new(&conn)?;
+        if !dbus.name_has_owner(SERVICE.try_into()?)? {
+            return Ok(vec![]);
+        }
+        Self::detect_on(&conn)
+    }
+
+    /// List the displays provided on the given connection.
+    fn detect_on(conn: &Connection) -> Result<Vec<Self>, anyhow::Error> {
+        let names = ScreenBrightnessProxyBlocking::new(conn)?
+            .displays_dbus_names()
+            .context("Failed to list displays of the compositor")?;
+
+        names
+            .into_iter()
+            .map(|name| {
+                let label = display_proxy(conn, &name)?.label()?;
+                Ok(SoftwareDisplay {
+                    label,
+                    name,
+                    conn: conn.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn open(&self) -> Result<Software, anyhow::Error> {
+        let proxy = display_proxy(&self.conn, &self.name)?;
+        let max = proxy.max_brightness()?;
+        if max <= 0 {
+            anyhow::bail!("Display {0} has a max brightness of {max}", self.label);
+        }
+        Ok(Software { proxy, max })
+    }
+}
+
+fn display_proxy(
+    conn: &Connection,
+    name: &str,
+) -> Result<ScreenBrightnessDisplayProxyBlocking<'static>, anyhow::Error> {
+    Ok(ScreenBrightnessDisplayProxyBlocking::builder(conn)
+        .path(format!("{PATH}/{name}"))?
+        .cache_properties(CacheProperties::No)
+        .build()?)
+}
+
+/// Display dimmed by the compositor, whose brightness range `0..=max` is scaled to a percentage.
+#[derive(Debug)]
+pub struct Software {
+    proxy: ScreenBrightnessDisplayProxyBlocking<'static>,
+    max: i32,
+}
+
+impl Backend for Software {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        check_code(code)?;
+        let raw = self.proxy.brightness()?.clamp(0, self.max);
+        Ok((raw as f64 * 100.0 / self.max as f64).round() as u16)
+    }
+
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        check_code(code)?;
+        let raw = (value.min(100) as f64 * self.max as f64 / 100.0).round() as i32;
+        Ok(self.proxy.set_brightness(raw, SUPPRESS_INDICATOR)?)
+    }
+}
+
+fn check_code(code: u8) -> Result<(), anyhow::Error> {
+    if code != BRIGHTNESS {
+        anyhow::bail!("Software brightness only supports brightness, not VCP feature {code:#04x}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use zbus::blocking::connection::Builder;
+
+    struct MockService {
+        names: Vec<String>,
+    }
+
+    #[zbus::interface(name = "org.kde.ScreenBrightness")]
+    impl MockService {
+        #[zbus(property, name = "DisplaysDBusNames")]
+        fn displays_dbus_names(&self) -> Vec<String> {
+            self.names.clone()
+        }
+    }
+
+    struct MockDisplay {
+        label: String,
+        brightness: i32,
+        max_brightness: i32,
+    }
+
+    #[zbus::interface(name = "org.kde.ScreenBrightness.Display")]
+    impl MockDisplay {
+        #[zbus(property)]
+        fn label(&self) -> String {
+            self.label.clone()
+        }
+
+        #[zbus(property)]
+        fn brightness(&self) -> i32 {
+            self.brightness
+        }
+
+        #[zbus(property)]
+        fn max_brightness(&self) -> i32 {
+            self.max_brightness
+        }
+
+        fn set_brightness(&mut self, brightness: i32, flags: u32) {
+            assert_eq!(SUPPRESS_INDICATOR, flags);
+            self.brightness = brightness.clamp(0, self.max_brightness);
+        }
+    }
+
+    /// Connect to a mock compositor with the given (label, brightness, max brightness) displays.
+    fn mock_service(displays: Vec<(&'static str, i32, i32)>) -> (Connection, Connection) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = thread::spawn(move || {
+            let names = (0..displays.len()).map(|i| format!("display{i}")).collect();
+            let mut builder = Builder::async_io_unix_stream(server)
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at(PATH, MockService { names })
+                .unwrap();
+            for (i, (label, brightness, max_brightness)) in displays.into_iter().enumerate() {
+                let display = MockDisplay {
+                    label: label.to_string(),
+                    brightness,
+                    max_brightness,
+                };
+                builder = builder
+                    .serve_at(format!("{PATH}/display{i}"), display)
+                    .unwrap();
+            }
+            builder.build().unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client).p2p().build().unwrap();
+        (client, server.join().unwrap())
+    }
+
+    #[test]
+    fn detect() {
+        let (conn, _server) =
+            mock_service(vec![("Built-in Screen", 500, 1000), ("LG HDR 4K", 50, 100)]);
+        let displays = SoftwareDisplay::detect_on(&conn).unwrap();
+        let found: Vec<_> = displays
+            .iter()
+            .map(|d| (d.label.as_str(), d.name.as_str()))
+            .collect();
+        assert_eq!(
+            vec![("Built-in Screen", "display0"), ("LG HDR 4K", "display1")],
+            found
+        );
+    }
+
+    #[test]
+    fn scaling() {
+        let (conn, _server) = mock_service(vec![("LG HDR 4K", 5000, 10000)]);
+        let display = &SoftwareDisplay::detect_on(&conn).unwrap()[0];
+        let mut software = display.open().unwrap();
+
+        assert_eq!(50, software.get(BRIGHTNESS).unwrap());
+        software.set(BRIGHTNESS, 33).unwrap();
+        assert_eq!(3300, software.proxy.brightness().unwrap());
+        assert_eq!(33, software.get(BRIGHTNESS).unwrap());
+
+        // Only brightness is supported
+        assert!(software.set(0x12, 50).is_err());
+    }
+}
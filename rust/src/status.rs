@@ -0,0 +1,115 @@
+/// Status of the running daemon, written to a file so it can be inspected by other commands.
+use crate::writer::WriteStats;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+/// Whether a monitor is working.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum Health {
+    Ok,
+    /// Updating the monitor failed with the given error. It is skipped, and periodically retried.
+    Degraded(String),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct MonitorStatus {
+    pub health: Health,
+    pub stats: WriteStats,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct DaemonStatus {
+    /// Process ID of the daemon
+    pub pid: u32,
+    /// Status of each monitor, keyed by the display's "manufacturer model serial connector" string
+    pub monitors: BTreeMap<String, MonitorStatus>,
+    /// Last lux value read from the sensor
+    pub lux: Option<f64>,
+}
+
+impl DaemonStatus {
+    pub fn read_from_file<P: AsRef<Path>>(file: P) -> Result<Self, anyhow::Error> {
+        Ok(ron::de::from_reader(BufReader::new(File::open(file)?))?)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, file: P) -> Result<(), anyhow::Error> {
+        let format_opts = ron::ser::PrettyConfig::new().indentor("  ");
+        ron::Options::default().to_io_writer_pretty(File::create(file)?, self, format_opts)?;
+        Ok(())
+    }
+
+    /// Whether the daemon that wrote this status is still running. Process IDs are reused, so the
+    /// process with its ID must also be running this program.
+    pub fn is_running(&self) -> bool {
+        match (process_name(self.pid), process_name(std::process::id())) {
+            (Some(daemon), Some(this)) => daemon == this,
+            _ => false,
+        }
+    }
+}
+
+/// Name of the program the process is running, or None if there is no such process.
+fn process_name(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    use std::process::Command;
+
+    #[test]
+    fn round_trip() {
+        let status = DaemonStatus {
+            pid: 1234,
+            monitors: BTreeMap::from([
+                (
+                    "GBT G27Q 2323".to_string(),
+                    MonitorStatus {
+                        health: Health::Ok,
+                        stats: WriteStats {
+                            writes: 10,
+                            retries: 2,
+                            ..Default::default()
+                        },
+                    },
+                ),
+                (
+                    "backlight intel_backlight".to_string(),
+                    MonitorStatus {
+                        health: Health::Degraded("Permission denied".to_string()),
+                        stats: WriteStats::default(),
+                    },
+                ),
+            ]),
+            lux: Some(120.5),
+        };
+        let dir = TempDir::new("status");
+        let path = dir.join("status.ron");
+        status.write_to_file(&path).unwrap();
+        assert_eq!(status, DaemonStatus::read_from_file(&path).unwrap());
+    }
+
+    #[test]
+    fn running() {
+        let status = |pid| DaemonStatus {
+            pid,
+            ..Default::default()
+        };
+        assert!(status(std::process::id()).is_running());
+
+        // Another program with a reused process ID isn't the daemon
+        let mut other = Command::new("sleep").arg("10").spawn().unwrap();
+        let is_running = status(other.id()).is_running();
+        other.kill().unwrap();
+        other.wait().unwrap();
+        assert!(!is_running);
+    }
+}
@@ -0,0 +1,91 @@
+/// Helpers shared by the tests of several modules.
+use crate::backend::Backend;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// In-memory backend, sharing its values so tests can inspect them.
+#[derive(Debug, Default, Clone)]
+pub struct FakeBackend {
+    pub values: Arc<Mutex<BTreeMap<u8, u16>>>,
+    pub writes: Arc<Mutex<u32>>,
+    /// Number of the next writes that fail
+    pub failing_writes: Arc<Mutex<u32>>,
+    /// Number of the next writes that seem to succeed, but don't change the value
+    pub ignored_writes: Arc<Mutex<u32>>,
+}
+
+/// Decrement the counter, returning whether it was above 0.
+fn take_one(counter: &Mutex<u32>) -> bool {
+    let mut counter = counter.lock().unwrap();
+    let was_set = *counter > 0;
+    *counter = counter.saturating_sub(1);
+    was_set
+}
+
+impl Backend for FakeBackend {
+    fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        Ok(self.values.lock().unwrap().get(&code).copied().unwrap_or(0))
+    }
+
+    fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        if take_one(&self.failing_writes) {
+            anyhow::bail!("Fake write failure");
+        }
+        *self.writes.lock().unwrap() += 1;
+        if !take_one(&self.ignored_writes) {
+            self.values.lock().unwrap().insert(code, value);
+        }
+        Ok(())
+    }
+}
+
+/// New empty directory under the system's temporary directory, removed again when dropped, even if
+/// the test fails.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a directory whose name includes `name`, to tell apart the directories of different tests.
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        loop {
+            let dir = std::env::temp_dir().join(format!(
+                "adaptive-brightness-{name}-{0}-{1}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            // Never reuse a directory, in case it belongs to another test
+            match fs::create_dir(&dir) {
+                Ok(()) => return TempDir(dir),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => panic!("Failed to create {dir:?}: {err}"),
+            }
+        }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,169 @@
+/// Gradual transitions between two values, e.g. when the brightness target changes.
+use crate::config::{Easing, TransitionConfig};
+
+use std::time::{Duration, Instant};
+
+/// An in-progress transition from one value to another.
+#[derive(Debug)]
+pub struct Transition {
+    from: u16,
+    to: u16,
+    duration: Duration,
+    easing: Easing,
+    start: Instant,
+}
+
+impl Transition {
+    /// Start a transition from `from` to `to`, with speed and shape depending on the direction.
+    pub fn start(from: u16, to: u16, config: &TransitionConfig) -> Self {
+        let speed = if to > from {
+            &config.brighten
+        } else {
+            &config.dim
+        };
+
+        let duration = match speed.duration_ms {
+            Some(ms) => Duration::from_millis(ms),
+            None => {
+                // Time to get there moving by `step` each interval
+                let steps = from.abs_diff(to) as f64 / speed.step.max(1) as f64;
+                Duration::from_millis(config.interval_ms).mul_f64(steps)
+            }
+        };
+
+        Transition {
+            from,
+            to,
+            duration,
+            easing: speed.easing,
+            start: Instant::now(),
+        }
+    }
+
+    /// The value this transition is heading to.
+    pub fn target(&self) -> u16 {
+        self.to
+    }
+
+    /// Time since the transition started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// The value the transition should be at after the given amount of time.
+    pub fn value_at(&self, elapsed: Duration) -> u16 {
+        if elapsed >= self.duration {
+            return self.to;
+        }
+
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        let from = self.from as f64;
+        let to = self.to as f64;
+
+        let value = match self.easing {
+            Easing::Linear => from + (to - from) * progress,
+            Easing::EaseOut => from + (to - from) * (1.0 - (1.0 - progress).powi(2)),
+            Easing::Gamma(gamma) => {
+                // Interpolate in gamma-encoded space, which is closer to how brightness is perceived
+                let from = from.powf(1.0 / gamma);
+                let to = to.powf(1.0 / gamma);
+                (from + (to - from) * progress).powf(gamma)
+            }
+        };
+
+        value.round() as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TransitionSpeed;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn default_steps() {
+        // Default: move by 2 every 100ms
+        let config = TransitionConfig::default();
+
+        let t = Transition::start(50, 60, &config);
+        assert_eq!(ms(500), t.duration);
+        for i in 0..=5 {
+            assert_eq!(50 + 2 * i as u16, t.value_at(ms(100 * i)));
+        }
+        assert_eq!(60, t.value_at(ms(1000)));
+
+        let t = Transition::start(60, 45, &config);
+        assert_eq!(ms(750), t.duration);
+        assert_eq!(58, t.value_at(ms(100)));
+        assert_eq!(46, t.value_at(ms(700)));
+        assert_eq!(45, t.value_at(ms(800)));
+    }
+
+    #[test]
+    fn fixed_duration() {
+        let config = TransitionConfig {
+            brighten: TransitionSpeed {
+                duration_ms: Some(1000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let t = Transition::start(0, 100, &config);
+        assert_eq!(0, t.value_at(ms(0)));
+        assert_eq!(25, t.value_at(ms(250)));
+        assert_eq!(50, t.value_at(ms(500)));
+        assert_eq!(100, t.value_at(ms(1000)));
+
+        let t = Transition::start(10, 20, &config);
+        assert_eq!(15, t.value_at(ms(500)));
+        assert_eq!(20, t.value_at(ms(1000)));
+    }
+
+    #[test]
+    fn direction_specific_speed() {
+        let config = TransitionConfig {
+            dim: TransitionSpeed {
+                step: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(ms(500), Transition::start(50, 60, &config).duration);
+        assert_eq!(ms(1000), Transition::start(60, 50, &config).duration);
+    }
+
+    #[test]
+    fn easing() {
+        let speed = |easing| TransitionSpeed {
+            duration_ms: Some(1000),
+            step: 2,
+            easing,
+        };
+        let config = |easing| TransitionConfig {
+            brighten: speed(easing),
+            dim: speed(easing),
+            ..Default::default()
+        };
+
+        let t = Transition::start(0, 100, &config(Easing::EaseOut));
+        assert_eq!(75, t.value_at(ms(500)));
+        assert_eq!(100, t.value_at(ms(1000)));
+
+        let t = Transition::start(100, 0, &config(Easing::EaseOut));
+        assert_eq!(25, t.value_at(ms(500)));
+
+        // Perceptually, halfway from 0 to 100 is much less than 50
+        let t = Transition::start(0, 100, &config(Easing::Gamma(2.0)));
+        assert_eq!(25, t.value_at(ms(500)));
+        assert_eq!(100, t.value_at(ms(1000)));
+
+        let t = Transition::start(100, 0, &config(Easing::Gamma(2.0)));
+        assert_eq!(25, t.value_at(ms(500)));
+    }
+}
@@ -0,0 +1,182 @@
+/// Long-lived threads that each own one monitor, so slow DDC/CI writes to one display don't delay
+/// the others.
+use crate::monitor::MonitorState;
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Work to do on a monitor's thread.
+type Job = Box<dyn FnOnce(&mut MonitorState) + Send>;
+
+/// A monitor owned by its own thread, which runs jobs on it in the order they were sent. The thread
+/// stops when the worker is dropped.
+///
+/// If a job panics, the monitor is marked degraded and the thread carries on with the next job, so
+/// the other monitors are not affected. Recovering sets all the monitor's features from scratch.
+#[derive(Debug)]
+pub struct MonitorWorker {
+    name: String,
+    device: String,
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MonitorWorker {
+    /// Start a thread for the monitor.
+    pub fn spawn(mut monitor: MonitorState) -> Self {
+        let (name, device) = (monitor.name().to_string(), monitor.device().to_string());
+        let (jobs, received) = mpsc::channel::<Job>();
+        let thread = thread::spawn(move || {
+            for job in received {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| job(&mut monitor))) {
+                    monitor
+                        .mark_degraded(anyhow::anyhow!("Panicked: {0}", panic_message(&payload)));
+                }
+            }
+        });
+        MonitorWorker {
+            name,
+            device,
+            jobs: Some(jobs),
+            thread: Some(thread),
+        }
+    }
+
+    /// Name of the display, in the form "manufacturer model serial connector".
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Physical path of the display, e.g. "i2c-6".
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// Run `f` on the monitor's thread after the jobs sent before it, without waiting for it. Its
+    /// result can be received from the returned channel, unless it panicked.
+    pub fn start<R, F>(&self, f: F) -> Receiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut MonitorState) -> R + Send + 'static,
+    {
+        let (result, received) = mpsc::channel();
+        let job: Job = Box::new(move |m| {
+            let _ = result.send(f(m));
+        });
+        // If the thread is gone, receiving the result fails
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(job);
+        }
+        received
+    }
+}
+
+impl Drop for MonitorWorker {
+    fn drop(&mut self) {
+        // Let the thread finish its remaining jobs and drop the monitor
+        drop(self.jobs.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Message of a panic, if it has one.
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown error"
+    }
+}
+
+/// Wait for the result of a job, or None if it panicked.
+fn wait<R>(result: &Receiver<R>) -> Option<R> {
+    result.recv().ok()
+}
+
+/// Run `f` on every monitor at the same time, each on its own thread. Returns the results in the
+/// same order as the workers, with None for the monitors where `f` panicked.
+pub fn run_all<R, F>(workers: &[MonitorWorker], f: F) -> Vec<Option<R>>
+where
+    R: Send + 'static,
+    F: FnOnce(&mut MonitorState) -> R + Clone + Send + 'static,
+{
+    let results: Vec<_> = workers.iter().map(|w| w.start(f.clone())).collect();
+    results.iter().map(wait).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WritePolicy;
+    use crate::monitor::BRIGHTNESS;
+    use crate::piecewise_linear::PiecewiseLinear;
+    use crate::status::Health;
+    use crate::test_util::FakeBackend;
+    use crate::writer::VcpWriter;
+
+    #[test]
+    fn jobs_in_order() {
+        let backends = [FakeBackend::default(), FakeBackend::default()];
+        let workers: Vec<_> = backends
+            .iter()
+            .zip(["i2c-6", "i2c-7"])
+            .map(|(backend, device)| {
+                MonitorWorker::spawn(MonitorState::for_display(
+                    "GBT G27Q 2323".to_string(),
+                    device.to_string(),
+                    VcpWriter::new(Box::new(backend.clone()), WritePolicy::default()),
+                    PiecewiseLinear::from_steps(vec![(0.0, 10), (250.0, 100)]).unwrap(),
+                ))
+            })
+            .collect();
+
+        for w in &workers {
+            w.start(|m| m.set_for_lux(0.0).unwrap());
+        }
+        let devices = run_all(&workers, |m| m.device().to_string());
+        assert_eq!(
+            vec![Some("i2c-6".to_string()), Some("i2c-7".to_string())],
+            devices
+        );
+        for backend in &backends {
+            assert_eq!(10, backend.values.lock().unwrap()[&BRIGHTNESS]);
+        }
+    }
+
+    #[test]
+    fn panic_degrades_one_monitor() {
+        let workers: Vec<_> = ["i2c-6", "i2c-7"]
+            .into_iter()
+            .map(|device| {
+                MonitorWorker::spawn(MonitorState::for_display(
+                    "GBT G27Q 2323".to_string(),
+                    device.to_string(),
+                    VcpWriter::new(Box::new(FakeBackend::default()), WritePolicy::default()),
+                    PiecewiseLinear::from_steps(vec![(0.0, 10), (250.0, 100)]).unwrap(),
+                ))
+            })
+            .collect();
+
+        let results = run_all(&workers, |m| {
+            if m.device() == "i2c-6" {
+                panic!("Test panic");
+            }
+            m.device().to_string()
+        });
+        assert_eq!(vec![None, Some("i2c-7".to_string())], results);
+
+        // The thread is still running, with the monitor degraded
+        let health = run_all(&workers, |m| m.status().health);
+        assert_eq!(
+            Some(Health::Degraded("Panicked: Test panic".to_string())),
+            health[0]
+        );
+        assert_eq!(Some(Health::Ok), health[1]);
+    }
+}
@@ -0,0 +1,231 @@
+/// Writes VCP values to a display, with optional read-back verification and retries, keeping count of
+/// errors.
+use crate::backend::Backend;
+use crate::config::WritePolicy;
+
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+/// Counters of reads and writes to a display.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct WriteStats {
+    /// Successful writes
+    pub writes: u64,
+    /// Attempts that failed and were retried
+    pub retries: u64,
+    /// Writes that read back a different value than was written
+    pub verify_mismatches: u64,
+    /// Reads or writes that still failed after all retries
+    pub failures: u64,
+    /// Failures since the last successful write
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug)]
+pub struct VcpWriter {
+    display: Box<dyn Backend>,
+    policy: WritePolicy,
+    stats: WriteStats,
+}
+
+impl VcpWriter {
+    pub fn new(display: Box<dyn Backend>, policy: WritePolicy) -> Self {
+        VcpWriter {
+            display,
+            policy,
+            stats: WriteStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &WriteStats {
+        &self.stats
+    }
+
+    /// Whether the display is connected over DDC/CI.
+    pub fn uses_ddc(&self) -> bool {
+        self.display.uses_ddc()
+    }
+
+    /// Use a new connection to the display, returning the old one.
+    pub fn replace_display(&mut self, display: Box<dyn Backend>) -> Box<dyn Backend> {
+        std::mem::replace(&mut self.display, display)
+    }
+
+    /// Whether there have been as many consecutive failures as the policy allows.
+    pub fn too_many_failures(&self) -> bool {
+        self.stats.consecutive_failures >= self.policy.max_consecutive_failures
+    }
+
+    /// Read the current value of a VCP feature.
+    pub fn get(&mut self, code: u8) -> Result<u16, anyhow::Error> {
+        let res = self.display.get(code);
+        if res.is_err() {
+            self.stats.failures += 1;
+        }
+        res
+    }
+
+    /// Set a VCP feature, retrying and verifying according to the write policy.
+    pub fn set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        let mut attempt = 0;
+        loop {
+            match self.try_set(code, value) {
+                Ok(()) => {
+                    self.stats.writes += 1;
+                    self.stats.consecutive_failures = 0;
+                    return Ok(());
+                }
+                Err(err) if attempt >= self.policy.retries => {
+                    self.stats.failures += 1;
+                    self.stats.consecutive_failures += 1;
+                    return Err(err.context(format!(
+                        "Failed to set VCP feature {code:#04x} to {value} after {0} attempts",
+                        attempt + 1
+                    )));
+                }
+                Err(err) => {
+                    eprintln!("Setting VCP feature {code:#04x} failed, retrying: {err:#}");
+                    self.stats.retries += 1;
+                    thread::sleep(self.policy.backoff(attempt));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Write the value once, then read it back if verification is enabled.
+    fn try_set(&mut self, code: u8, value: u16) -> Result<(), anyhow::Error> {
+        self.display.set(code, value)?;
+
+        if self.policy.verify {
+            let actual = self.display.get(code)?;
+            if actual != value {
+                self.stats.verify_mismatches += 1;
+                anyhow::bail!("Wrote {value} to VCP feature {code:#04x}, but read back {actual}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WritePolicy {
+    /// Delay before retrying after the given (zero-based) attempt failed.
+    fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_millis(self.backoff_ms.saturating_mul(1 << attempt.min(16)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::FakeBackend;
+
+    /// Writer to a fake display, without delays between retries.
+    fn writer(backend: &FakeBackend, policy: WritePolicy) -> VcpWriter {
+        let policy = WritePolicy {
+            backoff_ms: 0,
+            ..policy
+        };
+        VcpWriter::new(Box::new(backend.clone()), policy)
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = WritePolicy {
+            backoff_ms: 50,
+            ..Default::default()
+        };
+        assert_eq!(Duration::from_millis(50), policy.backoff(0));
+        assert_eq!(Duration::from_millis(100), policy.backoff(1));
+        assert_eq!(Duration::from_millis(200), policy.backoff(2));
+        assert_eq!(Duration::from_millis(400), policy.backoff(3));
+    }
+
+    #[test]
+    fn retry_then_succeed() {
+        let backend = FakeBackend::default();
+        *backend.failing_writes.lock().unwrap() = 2;
+        let mut w = writer(&backend, WritePolicy::default());
+
+        w.set(0x10, 40).unwrap();
+        assert_eq!(40, w.get(0x10).unwrap());
+        assert_eq!(
+            WriteStats {
+                writes: 1,
+                retries: 2,
+                ..Default::default()
+            },
+            *w.stats()
+        );
+
+        // One more failure than there are retries
+        *backend.failing_writes.lock().unwrap() = 3;
+        assert!(w.set(0x10, 50).is_err());
+        assert_eq!(40, w.get(0x10).unwrap());
+        assert_eq!(
+            (4, 1, 1),
+            (
+                w.stats().retries,
+                w.stats().failures,
+                w.stats().consecutive_failures
+            )
+        );
+    }
+
+    #[test]
+    fn verify_mismatch() {
+        let backend = FakeBackend::default();
+        *backend.ignored_writes.lock().unwrap() = 1;
+        let policy = WritePolicy {
+            verify: true,
+            ..Default::default()
+        };
+        let mut w = writer(&backend, policy);
+
+        w.set(0x10, 40).unwrap();
+        assert_eq!(40, w.get(0x10).unwrap());
+        assert_eq!(
+            WriteStats {
+                writes: 1,
+                retries: 1,
+                verify_mismatches: 1,
+                ..Default::default()
+            },
+            *w.stats()
+        );
+
+        // Without verification, the mismatch goes unnoticed
+        *backend.ignored_writes.lock().unwrap() = 1;
+        let mut w = writer(&backend, WritePolicy::default());
+        w.set(0x10, 50).unwrap();
+        assert_eq!(40, w.get(0x10).unwrap());
+        assert_eq!(0, w.stats().verify_mismatches);
+    }
+
+    #[test]
+    fn consecutive_failures() {
+        let backend = FakeBackend::default();
+        let policy = WritePolicy {
+            retries: 0,
+            max_consecutive_failures: 3,
+            ..Default::default()
+        };
+        let mut w = writer(&backend, policy);
+
+        // Degraded once the limit is reached
+        *backend.failing_writes.lock().unwrap() = 3;
+        for failures in 1..=3 {
+            assert!(!w.too_many_failures());
+            assert!(w.set(0x10, 40).is_err());
+            assert_eq!(failures, w.stats().consecutive_failures);
+        }
+        assert!(w.too_many_failures());
+
+        // A successful write resets the count, but not the total
+        w.set(0x10, 40).unwrap();
+        assert_eq!(0, w.stats().consecutive_failures);
+        assert_eq!(3, w.stats().failures);
+        assert!(!w.too_many_failures());
+    }
+}